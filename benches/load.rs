@@ -0,0 +1,67 @@
+//! Compare the load time of the JSON and binary native formats, and of memory mapping.
+//!
+//! Run with `cargo bench --bench load`.
+
+use std::{
+    hint::black_box,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use serdeio::write_record_to_file;
+use silva::{MultiOutputForest, binary::MappedModel, parser::read_lightgbm_model};
+
+const ITERATIONS: u32 = 200;
+
+fn measure<T>(name: &str, mut load: impl FnMut() -> T) -> Duration {
+    // warm up the page cache and allocator
+    black_box(load());
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(load());
+    }
+    let elapsed = start.elapsed() / ITERATIONS;
+    println!("{name:<8} {elapsed:>12.2?} per load");
+    elapsed
+}
+
+fn main() {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let model =
+        read_lightgbm_model(root.join("test_data/lightgbm/multiclass_classification/model.txt"))
+            .expect("Failed to read LightGBM model");
+
+    let dir = std::env::temp_dir().join(format!("silva-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let json_path = dir.join("model.json");
+    let binary_path = dir.join("model.bin");
+    write_record_to_file(&json_path, &model).unwrap();
+    model
+        .write_binary(std::fs::File::create(&binary_path).unwrap())
+        .unwrap();
+
+    for path in [&json_path, &binary_path] {
+        let size = std::fs::metadata(path).unwrap().len();
+        println!(
+            "{:<8} {size:>12} bytes",
+            path.extension().unwrap().display()
+        );
+    }
+    let json = measure("json", || {
+        MultiOutputForest::<f64>::from_file(&json_path).unwrap()
+    });
+    let binary = measure("binary", || {
+        let bytes = std::fs::read(&binary_path).unwrap();
+        MultiOutputForest::<f64>::read_binary(bytes.as_slice()).unwrap()
+    });
+    let mapped = measure("mmap", || MappedModel::<f64>::open(&binary_path).unwrap());
+    let verified = measure("verified", || {
+        MappedModel::<f64>::open_verified(&binary_path).unwrap()
+    });
+    for (name, elapsed) in [("binary", binary), ("mmap", mapped), ("verified", verified)] {
+        let speedup = json.as_secs_f64() / elapsed.as_secs_f64();
+        println!("{name:<8} {speedup:>12.1}x faster than json");
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
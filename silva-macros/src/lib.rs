@@ -0,0 +1,234 @@
+//! Procedural macros for the [`silva`](https://crates.io/crates/silva) tree ensemble inference engine.
+
+use std::path::PathBuf;
+
+use proc_macro::TokenStream;
+use quote::quote;
+use silva::{
+    MultiOutputForest,
+    codegen::{CodegenStyle, RustCodegenOptions, generate_rust_module},
+    parser::{read_lightgbm_model, read_xgboost_model},
+};
+use syn::{
+    Data, DeriveInput, Fields, Ident, LitStr, Token,
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+};
+
+struct IncludeModelInput {
+    path: LitStr,
+    format: Option<LitStr>,
+    style: Option<LitStr>,
+    precision: Option<LitStr>,
+}
+
+impl Parse for IncludeModelInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let path: LitStr = input.parse()?;
+        let mut format = None;
+        let mut style = None;
+        let mut precision = None;
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            let key: Ident = input.parse()?;
+            input.parse::<Token![=]>()?;
+            let value: LitStr = input.parse()?;
+            match key.to_string().as_str() {
+                "format" => format = Some(value),
+                "style" => style = Some(value),
+                "precision" => precision = Some(value),
+                _ => {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        "unknown option, expected `format`, `style` or `precision`",
+                    ));
+                }
+            }
+        }
+        Ok(Self {
+            path,
+            format,
+            style,
+            precision,
+        })
+    }
+}
+
+/// Parse a model file at compile time and expand to generated prediction code.
+///
+/// ```ignore
+/// mod model {
+///     silva_macros::include_model!("model.json", format = "xgboost");
+/// }
+///
+/// let raw: [f64; model::N_OUTPUTS] = model::predict(&[1.5, 2.3, 0.8]);
+/// ```
+///
+/// The path is relative to the `CARGO_MANIFEST_DIR` of the calling crate. Options:
+/// - `format`: `"silva"` (default), `"xgboost"` or `"lightgbm"`
+/// - `style`: `"branches"` (default) for nested `if`/`else` code, or `"table"` for a const
+///   flat node table
+/// - `precision`: `"f64"` (default) or `"f32"`, the float type of thresholds, inputs and outputs
+///
+/// The expansion is self-contained: the resulting binary needs neither the model file nor
+/// `silva` at runtime.
+#[proc_macro]
+pub fn include_model(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as IncludeModelInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(input: &IncludeModelInput) -> syn::Result<proc_macro2::TokenStream> {
+    let manifest_dir =
+        std::env::var("CARGO_MANIFEST_DIR").map_err(|e| syn::Error::new(input.path.span(), e))?;
+    let path = PathBuf::from(manifest_dir).join(input.path.value());
+
+    let format = input
+        .format
+        .as_ref()
+        .map_or_else(|| "silva".to_string(), LitStr::value);
+    let model = match format.as_str() {
+        "silva" => MultiOutputForest::from_file(&path).map_err(|e| e.to_string()),
+        "xgboost" => read_xgboost_model(&path).map_err(|e| e.to_string()),
+        "lightgbm" => read_lightgbm_model(&path).map_err(|e| e.to_string()),
+        _ => {
+            let span = input.format.as_ref().unwrap_or(&input.path).span();
+            return Err(syn::Error::new(
+                span,
+                "unknown format, expected \"silva\", \"xgboost\" or \"lightgbm\"",
+            ));
+        }
+    }
+    .map_err(|e| {
+        syn::Error::new(
+            input.path.span(),
+            format!("failed to load model from {}: {e}", path.display()),
+        )
+    })?;
+
+    let style = match input.style.as_ref().map(LitStr::value).as_deref() {
+        None | Some("branches") => CodegenStyle::Branches,
+        Some("table") => CodegenStyle::NodeTable,
+        Some(_) => {
+            return Err(syn::Error::new(
+                input.style.as_ref().unwrap().span(),
+                "unknown style, expected \"branches\" or \"table\"",
+            ));
+        }
+    };
+
+    let options = RustCodegenOptions { style };
+    let code = match input.precision.as_ref().map(LitStr::value).as_deref() {
+        None | Some("f64") => generate_rust_module(&model, &options),
+        Some("f32") => generate_rust_module(&model.cast::<f32>(), &options),
+        Some(_) => {
+            return Err(syn::Error::new(
+                input.precision.as_ref().unwrap().span(),
+                "unknown precision, expected \"f64\" or \"f32\"",
+            ));
+        }
+    };
+    let code: proc_macro2::TokenStream = code
+        .parse()
+        .map_err(|e| syn::Error::new(input.path.span(), format!("{e}")))?;
+
+    // make the calling crate rebuild whenever the model file changes
+    let path = path.to_string_lossy();
+    Ok(quote! {
+        const _: &[u8] = include_bytes!(#path);
+        #code
+    })
+}
+
+/// Derive `silva::features::NamedFeatures` for a struct with named fields.
+///
+/// ```ignore
+/// #[derive(silva_macros::NamedFeatures)]
+/// struct Applicant {
+///     age: u32,
+///     #[silva(rename = "annual_income")]
+///     income: Option<f64>,
+///     #[silva(skip)]
+///     name: String,
+/// }
+///
+/// let scores = model.predict_record(&applicant)?;
+/// ```
+///
+/// Every field is a feature named after the field, unless it has `#[silva(rename = "...")]` or
+/// `#[silva(skip)]`. Field types must implement `silva::features::FeatureValue`.
+#[proc_macro_derive(NamedFeatures, attributes(silva))]
+pub fn derive_named_features(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_named_features(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand_named_features(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "NamedFeatures needs a struct with named fields",
+                ));
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "NamedFeatures can only be derived for structs",
+            ));
+        }
+    };
+
+    let mut visits = Vec::new();
+    for field in fields {
+        let Some(ident) = &field.ident else {
+            continue;
+        };
+        let mut name = ident.to_string();
+        let mut skip = false;
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("silva"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    name = meta.value()?.parse::<LitStr>()?.value();
+                    Ok(())
+                } else if meta.path.is_ident("skip") {
+                    skip = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("unknown option, expected `rename` or `skip`"))
+                }
+            })?;
+        }
+        if !skip {
+            visits.push(quote! {
+                visit(#name, ::silva::features::FeatureValue::to_f64(&self.#ident));
+            });
+        }
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::silva::features::NamedFeatures for #ident #ty_generics #where_clause {
+            fn visit_features(&self, visit: &mut dyn FnMut(&str, f64)) {
+                #(#visits)*
+            }
+        }
+    })
+}
@@ -0,0 +1,115 @@
+use std::{fs::read_to_string, path::Path};
+
+use silva::{
+    MultiOutputForest,
+    parser::{read_lightgbm_model, read_xgboost_model},
+};
+
+mod xgboost_regression {
+    silva_macros::include_model!(
+        "../test_data/xgboost/regression/model.json",
+        format = "xgboost"
+    );
+}
+
+mod xgboost_multiclass_table {
+    silva_macros::include_model!(
+        "../test_data/xgboost/multiclass_classification/model.json",
+        format = "xgboost",
+        style = "table",
+    );
+}
+
+mod lightgbm_binary {
+    silva_macros::include_model!(
+        "../test_data/lightgbm/binary_classification/model.txt",
+        format = "lightgbm",
+    );
+}
+
+mod xgboost_binary_f32 {
+    silva_macros::include_model!(
+        "../test_data/xgboost/binary_classification/model.json",
+        format = "xgboost",
+        precision = "f32",
+    );
+}
+
+mod native_multiclass {
+    silva_macros::include_model!("../test_data/native/multiclass_classification/model.json");
+}
+
+fn read_features(path: impl AsRef<Path>) -> Vec<Vec<f64>> {
+    read_to_string(path)
+        .expect("Failed to read X")
+        .lines()
+        .map(|line| {
+            line.split(',')
+                .map(|s| s.parse::<f64>().expect("Failed to parse X value"))
+                .collect()
+        })
+        .collect()
+}
+
+fn assert_same_predictions(
+    data_dir: &str,
+    model: &MultiOutputForest,
+    predict: impl Fn(&[f64]) -> Vec<f64>,
+) {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+    for x in read_features(root.join(data_dir).join("X.csv")) {
+        let expected: Vec<f64> = model.predict(&x).iter().map(|v| v.into_inner()).collect();
+        assert_eq!(predict(&x), expected);
+    }
+}
+
+#[test]
+fn test_xgboost_regression() {
+    let model = read_xgboost_model("../test_data/xgboost/regression/model.json").unwrap();
+    assert_eq!(xgboost_regression::N_OUTPUTS, 1);
+    assert_same_predictions("test_data/xgboost/regression", &model, |x| {
+        xgboost_regression::predict(x).to_vec()
+    });
+}
+
+#[test]
+fn test_xgboost_multiclass_table() {
+    let model =
+        read_xgboost_model("../test_data/xgboost/multiclass_classification/model.json").unwrap();
+    assert_eq!(xgboost_multiclass_table::N_OUTPUTS, 3);
+    assert_same_predictions("test_data/xgboost/multiclass_classification", &model, |x| {
+        xgboost_multiclass_table::predict(x).to_vec()
+    });
+}
+
+#[test]
+fn test_xgboost_binary_f32() {
+    let model = read_xgboost_model("../test_data/xgboost/binary_classification/model.json")
+        .unwrap()
+        .cast::<f32>();
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+    for x in read_features(root.join("test_data/xgboost/binary_classification/X.csv")) {
+        let x: Vec<f32> = x.iter().map(|&v| v as f32).collect();
+        let expected: Vec<f32> = model.predict(&x).iter().map(|v| v.into_inner()).collect();
+        assert_eq!(xgboost_binary_f32::predict(&x).to_vec(), expected);
+    }
+}
+
+#[test]
+fn test_lightgbm_binary() {
+    let model =
+        read_lightgbm_model("../test_data/lightgbm/binary_classification/model.txt").unwrap();
+    assert_same_predictions("test_data/lightgbm/binary_classification", &model, |x| {
+        lightgbm_binary::predict(x).to_vec()
+    });
+}
+
+#[test]
+fn test_native_multiclass() {
+    let model =
+        MultiOutputForest::from_file("../test_data/native/multiclass_classification/model.json")
+            .unwrap();
+    assert_same_predictions("test_data/native/multiclass_classification", &model, |x| {
+        native_multiclass::predict(x).to_vec()
+    });
+}
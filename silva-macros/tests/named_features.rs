@@ -0,0 +1,59 @@
+use std::path::Path;
+
+use silva::{features::NamedFeatures, parser::read_lightgbm_model};
+use silva_macros::NamedFeatures;
+
+#[derive(NamedFeatures)]
+struct Row {
+    #[silva(rename = "Column_0")]
+    a: f64,
+    #[silva(rename = "Column_1")]
+    b: f32,
+    #[silva(rename = "Column_2")]
+    c: Option<f64>,
+    #[silva(rename = "Column_3")]
+    d: i32,
+    #[silva(rename = "Column_4")]
+    e: bool,
+    #[silva(skip)]
+    #[allow(dead_code)]
+    label: String,
+}
+
+#[derive(NamedFeatures)]
+struct Plain {
+    age: u8,
+}
+
+#[test]
+fn test_derive_named_features() {
+    let row = Row {
+        a: 0.5,
+        b: -1.25,
+        c: None,
+        d: 3,
+        e: true,
+        label: "ignored".to_string(),
+    };
+    let mut visited = Vec::new();
+    row.visit_features(&mut |name, value| visited.push((name.to_string(), value)));
+    assert_eq!(visited.len(), 5);
+    assert_eq!(visited[0], ("Column_0".to_string(), 0.5));
+    assert_eq!(visited[1], ("Column_1".to_string(), -1.25));
+    assert_eq!(visited[2].0, "Column_2");
+    assert!(visited[2].1.is_nan());
+    assert_eq!(visited[3], ("Column_3".to_string(), 3.0));
+    assert_eq!(visited[4], ("Column_4".to_string(), 1.0));
+
+    let mut visited = Vec::new();
+    Plain { age: 7 }.visit_features(&mut |name, value| visited.push((name.to_string(), value)));
+    assert_eq!(visited, [("age".to_string(), 7.0)]);
+
+    let path =
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("../test_data/lightgbm/regression/model.txt");
+    let model = read_lightgbm_model(path).unwrap();
+    let expected = model
+        .try_predict(&[0.5, -1.25, f64::NAN, 3.0, 1.0])
+        .unwrap();
+    assert_eq!(model.predict_record(&row).unwrap(), expected);
+}
@@ -0,0 +1,345 @@
+//! Predictions on Apache Arrow record batches, enabled by the `arrow` feature.
+
+use arrow_array::{Array, Float64Array, RecordBatch, cast::AsArray, types::*};
+use arrow_schema::DataType;
+use rustc_hash::FxHashMap;
+use thiserror::Error;
+
+use crate::{Error, Float, MultiOutputForest, TreeNode, features::CategoryCodes};
+
+/// Custom error types for Arrow input
+#[derive(Debug, Error)]
+pub enum ArrowInputError {
+    #[error("The model has no feature names")]
+    MissingFeatureNames,
+    #[error("Missing column for feature: {name}")]
+    MissingColumn { name: String },
+    #[error("Unsupported type {data_type} of column {name}")]
+    UnsupportedColumn { name: String, data_type: String },
+    #[error("No category codes for string column {name}")]
+    MissingCategoryCodes { name: String },
+    #[error("Unknown category {category} in column {name}")]
+    UnknownCategory { name: String, category: String },
+    #[error("Prediction failed at row {row}: {source}")]
+    Prediction {
+        row: usize,
+        #[source]
+        source: Error,
+    },
+}
+
+/// Value of a column at a row as `f64`, read from the Arrow buffers in place.
+type ColumnReader<'a> = Box<dyn Fn(usize) -> f64 + 'a>;
+
+macro_rules! numeric {
+    ($array:expr, $t:ty) => {{
+        let array = $array.as_primitive::<$t>();
+        Box::new(move |r| {
+            if array.is_null(r) {
+                f64::NAN
+            } else {
+                array.value(r) as f64
+            }
+        })
+    }};
+}
+
+/// Reader of a column, with nulls as NaN.
+///
+/// Dictionary columns are decoded when their values are numeric, and string dictionaries are
+/// encoded with the category codes of the feature.
+fn column_reader<'a>(
+    name: &str,
+    array: &'a dyn Array,
+    codes: Option<&FxHashMap<String, usize>>,
+) -> Result<ColumnReader<'a>, ArrowInputError> {
+    let reader: ColumnReader<'a> = match array.data_type() {
+        DataType::Float64 => numeric!(array, Float64Type),
+        DataType::Float32 => numeric!(array, Float32Type),
+        DataType::Int8 => numeric!(array, Int8Type),
+        DataType::Int16 => numeric!(array, Int16Type),
+        DataType::Int32 => numeric!(array, Int32Type),
+        DataType::Int64 => numeric!(array, Int64Type),
+        DataType::UInt8 => numeric!(array, UInt8Type),
+        DataType::UInt16 => numeric!(array, UInt16Type),
+        DataType::UInt32 => numeric!(array, UInt32Type),
+        DataType::UInt64 => numeric!(array, UInt64Type),
+        DataType::Boolean => {
+            let array = array.as_boolean();
+            Box::new(move |r| match array.is_null(r) {
+                true => f64::NAN,
+                false => f64::from(u8::from(array.value(r))),
+            })
+        }
+        DataType::Dictionary(_, _) => {
+            let dictionary = array.as_any_dictionary();
+            let keys = column_reader(name, dictionary.keys(), None)?;
+            let values = match category_codes(name, dictionary.values().as_ref(), codes)? {
+                Some(codes) => Box::new(move |key| codes[key]),
+                None => column_reader(name, dictionary.values().as_ref(), None)?,
+            };
+            Box::new(move |r| match array.is_null(r) {
+                true => f64::NAN,
+                false => values(keys(r) as usize),
+            })
+        }
+        data_type => {
+            return Err(ArrowInputError::UnsupportedColumn {
+                name: name.to_string(),
+                data_type: data_type.to_string(),
+            });
+        }
+    };
+    Ok(reader)
+}
+
+/// Codes of the values of a string dictionary, `None` for other dictionaries.
+fn category_codes(
+    name: &str,
+    values: &dyn Array,
+    codes: Option<&FxHashMap<String, usize>>,
+) -> Result<Option<Vec<f64>>, ArrowInputError> {
+    let categories: Vec<Option<&str>> = match values.data_type() {
+        DataType::Utf8 => values.as_string::<i32>().iter().collect(),
+        DataType::LargeUtf8 => values.as_string::<i64>().iter().collect(),
+        DataType::Utf8View => values.as_string_view().iter().collect(),
+        _ => return Ok(None),
+    };
+    let codes = codes.ok_or_else(|| ArrowInputError::MissingCategoryCodes {
+        name: name.to_string(),
+    })?;
+    categories
+        .into_iter()
+        .map(|category| match category {
+            None => Ok(f64::NAN),
+            Some(category) => codes.get(category).map(|&code| code as f64).ok_or_else(|| {
+                ArrowInputError::UnknownCategory {
+                    name: name.to_string(),
+                    category: category.to_string(),
+                }
+            }),
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+impl<F: Float> MultiOutputForest<F> {
+    /// Predict every row of `batch`, returning one array per output.
+    ///
+    /// Columns are matched to the model's feature names; other columns are ignored and nulls are
+    /// missing values. Numeric, boolean and dictionary columns are supported and read in place.
+    /// String dictionaries are refused, see [`Self::predict_record_batch_with_categories`].
+    ///
+    /// Rows are predicted with the checks of [`Self::try_predict`], so a null at a split without
+    /// default direction is an [`ArrowInputError::Prediction`].
+    pub fn predict_record_batch(
+        &self,
+        batch: &RecordBatch,
+    ) -> Result<Vec<Float64Array>, ArrowInputError> {
+        self.predict_record_batch_with_categories(batch, &CategoryCodes::new())
+    }
+
+    /// Like [`Self::predict_record_batch`], encoding string dictionaries with the category
+    /// codes the model was trained with. Categories without a code are errors.
+    pub fn predict_record_batch_with_categories(
+        &self,
+        batch: &RecordBatch,
+        categories: &CategoryCodes,
+    ) -> Result<Vec<Float64Array>, ArrowInputError> {
+        let feature_names = &self.metadata().feature_names;
+        if feature_names.is_empty() {
+            return Err(ArrowInputError::MissingFeatureNames);
+        }
+        let columns = feature_names
+            .iter()
+            .map(|name| {
+                let column = batch
+                    .column_by_name(name)
+                    .ok_or_else(|| ArrowInputError::MissingColumn { name: name.clone() })?;
+                column_reader(name, column.as_ref(), categories.of(name))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let outputs = self
+            .forests
+            .iter()
+            .map(|forest| {
+                (0..batch.num_rows())
+                    .map(|row| {
+                        let feature = |node: &TreeNode<F>| {
+                            let column =
+                                columns.get(node.split_index).ok_or(Error::FeatureIndex {
+                                    feature: node.split_index,
+                                    node: node.id,
+                                    actual: columns.len(),
+                                })?;
+                            Ok(F::from_f64(column(row)))
+                        };
+                        forest
+                            .try_predict_by(feature)
+                            .map(|prediction| prediction.into_inner().into())
+                            .map_err(|source| ArrowInputError::Prediction { row, source })
+                    })
+                    .collect::<Result<Float64Array, _>>()
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(outputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, sync::Arc};
+
+    use arrow_array::{ArrayRef, DictionaryArray, Float32Array, StringArray};
+
+    use super::*;
+    use crate::{
+        Forest, Tree,
+        parser::{read_lightgbm_model, test_utils::read_features},
+    };
+
+    #[test]
+    fn test_predict_record_batch() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/lightgbm/multiclass_classification");
+        let model = read_lightgbm_model(data_dir.join("model.txt")).unwrap();
+
+        let mut rows = read_features(&data_dir.join("X.csv"));
+        for (r, row) in rows.iter_mut().enumerate() {
+            if r % 4 == 0 {
+                row[r % 5] = f64::NAN;
+            }
+        }
+        let column = |i: usize| -> Vec<Option<f64>> {
+            rows.iter()
+                .map(|row| Some(row[i]).filter(|v| !v.is_nan()))
+                .collect()
+        };
+
+        // shuffled columns of different types, plus a column unknown to the model
+        let f32_column: Float32Array = column(1).into_iter().map(|v| v.map(|v| v as f32)).collect();
+        let dictionary: DictionaryArray<Int32Type> = {
+            let values = column(3);
+            let keys: Vec<Option<i32>> = (0..values.len())
+                .map(|i| values[i].map(|_| i as i32))
+                .collect();
+            let values = Float64Array::from(values);
+            DictionaryArray::try_new(keys.into(), Arc::new(values)).unwrap()
+        };
+        let ids: StringArray = (0..rows.len()).map(|i| Some(format!("id{i}"))).collect();
+        let batch = RecordBatch::try_from_iter([
+            (
+                "Column_4",
+                Arc::new(Float64Array::from(column(4))) as ArrayRef,
+            ),
+            ("id", Arc::new(ids) as ArrayRef),
+            ("Column_3", Arc::new(dictionary) as ArrayRef),
+            (
+                "Column_2",
+                Arc::new(Float64Array::from(column(2))) as ArrayRef,
+            ),
+            ("Column_1", Arc::new(f32_column) as ArrayRef),
+            (
+                "Column_0",
+                Arc::new(Float64Array::from(column(0))) as ArrayRef,
+            ),
+        ])
+        .unwrap();
+
+        let outputs = model.predict_record_batch(&batch).unwrap();
+        assert_eq!(outputs.len(), 3);
+        for (r, row) in rows.iter_mut().enumerate() {
+            row[1] = row[1] as f32 as f64;
+            for (output, expected) in outputs.iter().zip(model.predict(row)) {
+                assert_eq!(output.value(r), expected.into_inner());
+            }
+        }
+
+        let batch = batch.project(&[0, 1, 2, 3, 4]).unwrap();
+        assert!(matches!(
+            model.predict_record_batch(&batch),
+            Err(ArrowInputError::MissingColumn { name }) if name == "Column_0"
+        ));
+    }
+
+    #[test]
+    fn test_categorical_dictionary() {
+        // string dictionaries need the category codes from training, whatever their keys
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/lightgbm/regression");
+        let model = read_lightgbm_model(data_dir.join("model.txt")).unwrap();
+        let rows = read_features(&data_dir.join("X.csv"));
+        let column = |i: usize| -> ArrayRef {
+            Arc::new(Float64Array::from_iter_values(
+                rows[..3].iter().map(|row| row[i]),
+            ))
+        };
+        let keys = arrow_array::Int8Array::from(vec![Some(1), None, Some(0)]);
+        let values = StringArray::from(vec!["low", "high"]);
+        let dictionary = DictionaryArray::try_new(keys, Arc::new(values)).unwrap();
+        let batch = RecordBatch::try_from_iter([
+            ("Column_0", Arc::new(dictionary) as ArrayRef),
+            ("Column_1", column(1)),
+            ("Column_2", column(2)),
+            ("Column_3", column(3)),
+            ("Column_4", column(4)),
+        ])
+        .unwrap();
+
+        assert!(matches!(
+            model.predict_record_batch(&batch),
+            Err(ArrowInputError::MissingCategoryCodes { name }) if name == "Column_0"
+        ));
+        let categories = CategoryCodes::new().with_feature("Column_0", ["low"]);
+        assert!(matches!(
+            model.predict_record_batch_with_categories(&batch, &categories),
+            Err(ArrowInputError::UnknownCategory { name, category })
+                if name == "Column_0" && category == "high"
+        ));
+
+        let categories = CategoryCodes::new().with_feature("Column_0", ["high", "medium", "low"]);
+        let outputs = model
+            .predict_record_batch_with_categories(&batch, &categories)
+            .unwrap();
+        for (r, code) in [0.0, f64::NAN, 2.0].into_iter().enumerate() {
+            let mut row = rows[r].clone();
+            row[0] = code;
+            assert_eq!(outputs[0].value(r), model.predict(&row)[0].into_inner());
+        }
+    }
+
+    #[test]
+    fn test_null_without_default_direction() {
+        let tree = Tree::from_nodes(vec![
+            TreeNode::split(0, 1, 1.0, 1, 2),
+            TreeNode::leaf(1, 1.0),
+            TreeNode::leaf(2, 2.0),
+        ]);
+        let mut model = MultiOutputForest::new(vec![Forest::new(0.0, vec![tree])]);
+        model.metadata_mut().feature_names = vec!["a".to_string(), "b".to_string()];
+        let batch = RecordBatch::try_from_iter([
+            (
+                "a",
+                Arc::new(Float64Array::from(vec![0.0, 0.0])) as ArrayRef,
+            ),
+            (
+                "b",
+                Arc::new(Float64Array::from(vec![Some(3.0), None])) as ArrayRef,
+            ),
+        ])
+        .unwrap();
+        assert!(matches!(
+            model.predict_record_batch(&batch),
+            Err(ArrowInputError::Prediction {
+                row: 1,
+                source: Error::MissingValue {
+                    feature: 1,
+                    node: 0
+                }
+            })
+        ));
+        let batch = batch.slice(0, 1);
+        assert_eq!(model.predict_record_batch(&batch).unwrap()[0].value(0), 2.0);
+    }
+}
@@ -0,0 +1,661 @@
+//! Compact binary serialization of [`MultiOutputForest`].
+//!
+//! A file starts with a fixed header followed by a section table and the sections themselves:
+//!
+//! ```text
+//! header        magic "SILVABIN", format version: u32, float width in bytes: u32,
+//!               section count: u32, CRC32 of the section table: u32
+//! section table one entry per section: kind: u32, CRC32 of the content: u32,
+//!               offset: u64, length in bytes: u64
+//! sections      little-endian arrays, each starting at a multiple of 8 bytes
+//! ```
+//!
+//! Node fields are stored column by column over all trees, and child references are positions
+//! within their tree, so every array can be used in place once the file is in memory, see
+//! [`MultiOutputForestView`] and [`MappedModel`].
+
+mod view;
+
+use std::io::{Read, Write};
+
+use ordered_float::NotNan;
+use thiserror::Error;
+
+use crate::{Float, Forest, MultiOutputForest, Tree, TreeNode, map::FxIndexMap};
+
+pub use view::{MappedModel, MultiOutputForestView};
+
+pub const MAGIC: &[u8; 8] = b"SILVABIN";
+pub const FORMAT_VERSION: u32 = 1;
+
+pub(crate) const HEADER_LEN: usize = 24;
+pub(crate) const SECTION_ENTRY_LEN: usize = 24;
+pub(crate) const SECTION_ALIGN: usize = 8;
+/// Child position of leaves.
+pub(crate) const NO_CHILD: u32 = u32::MAX;
+
+/// Kinds of the sections of the binary format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub(crate) enum SectionKind {
+    /// `u32` tree offsets, output `i` owns trees `[off[i], off[i + 1])`.
+    ForestOffsets = 1,
+    /// Float base value of each output.
+    BaseValues = 2,
+    /// `u32` node offsets, tree `i` owns nodes `[off[i], off[i + 1])`.
+    TreeOffsets = 3,
+    /// `u32` position of the root of each tree.
+    TreeRoots = 4,
+    /// `u32` node ids.
+    NodeIds = 5,
+    /// `u32` feature index of each split.
+    NodeSplitIndices = 6,
+    /// Float split thresholds.
+    NodeSplitConditions = 7,
+    /// Float node values.
+    NodeValues = 8,
+    /// `u32` position of the left child within its tree, [`NO_CHILD`] for leaves.
+    NodeLefts = 9,
+    /// `u32` position of the right child within its tree, [`NO_CHILD`] for leaves.
+    NodeRights = 10,
+    /// `u8` missing value direction: 0 right, 1 left, 2 undefined.
+    NodeDefaultLefts = 11,
+    /// JSON encoded [`ModelMetadata`](crate::ModelMetadata), only present when not empty.
+    Metadata = 12,
+    /// Float node covers, NaN when unknown; only present when some node has one.
+    NodeCovers = 13,
+    /// Float expected outputs of split nodes, NaN when unknown; only present when some node has
+    /// one.
+    NodeInternalValues = 14,
+    /// Float split gains, NaN when unknown; only present when some node has one.
+    NodeGains = 15,
+    /// Float node hessian sums, NaN when unknown; only present when some node has one.
+    NodeHessians = 16,
+}
+
+impl SectionKind {
+    /// Sections holding the trees, present in every file.
+    const COLUMNS: [SectionKind; 11] = [
+        SectionKind::ForestOffsets,
+        SectionKind::BaseValues,
+        SectionKind::TreeOffsets,
+        SectionKind::TreeRoots,
+        SectionKind::NodeIds,
+        SectionKind::NodeSplitIndices,
+        SectionKind::NodeSplitConditions,
+        SectionKind::NodeValues,
+        SectionKind::NodeLefts,
+        SectionKind::NodeRights,
+        SectionKind::NodeDefaultLefts,
+    ];
+}
+
+/// Custom error types for the binary model format
+#[derive(Debug, Error)]
+pub enum BinaryFormatError {
+    #[error("IO error: {source}")]
+    Io {
+        #[from]
+        source: std::io::Error,
+    },
+    #[error("Not a Silva binary model: invalid magic header")]
+    InvalidMagic,
+    #[error("Unsupported binary format version {version}, expected at most {FORMAT_VERSION}")]
+    UnsupportedVersion { version: u32 },
+    #[error("Unsupported float width of {width} bytes, expected 4 or 8")]
+    UnsupportedFloatWidth { width: u32 },
+    #[error("Checksum mismatch in {section}")]
+    ChecksumMismatch { section: String },
+    #[error("Missing section {section:?}")]
+    MissingSection { section: String },
+    #[error("Malformed binary model: {message}")]
+    Malformed { message: String },
+    #[error("Cannot use binary model in place: {message}")]
+    ZeroCopy { message: String },
+}
+
+fn malformed(message: impl Into<String>) -> BinaryFormatError {
+    BinaryFormatError::Malformed {
+        message: message.into(),
+    }
+}
+
+/// Sections of a binary model, borrowed from the underlying bytes.
+pub(crate) struct Sections<'a> {
+    pub(crate) float_width: u32,
+    sections: Vec<(u32, &'a [u8])>,
+}
+
+impl<'a> Sections<'a> {
+    /// Validate the header and section table of `bytes`, and the section checksums if `verify`.
+    pub(crate) fn parse(bytes: &'a [u8], verify: bool) -> Result<Self, BinaryFormatError> {
+        if bytes.len() < HEADER_LEN || &bytes[..8] != MAGIC {
+            return Err(BinaryFormatError::InvalidMagic);
+        }
+        let version = read_u32(bytes, 8);
+        if version == 0 || version > FORMAT_VERSION {
+            return Err(BinaryFormatError::UnsupportedVersion { version });
+        }
+        let float_width = read_u32(bytes, 12);
+        if float_width != 4 && float_width != 8 {
+            return Err(BinaryFormatError::UnsupportedFloatWidth { width: float_width });
+        }
+        let section_count = read_u32(bytes, 16) as usize;
+        let table_crc = read_u32(bytes, 20);
+
+        let table_end = section_count
+            .checked_mul(SECTION_ENTRY_LEN)
+            .and_then(|len| len.checked_add(HEADER_LEN))
+            .filter(|&end| end <= bytes.len())
+            .ok_or_else(|| malformed("truncated section table"))?;
+        let table = &bytes[HEADER_LEN..table_end];
+        if crc32fast::hash(table) != table_crc {
+            return Err(BinaryFormatError::ChecksumMismatch {
+                section: "section table".to_string(),
+            });
+        }
+
+        let mut sections = Vec::with_capacity(section_count);
+        for entry in table.chunks_exact(SECTION_ENTRY_LEN) {
+            let kind = read_u32(entry, 0);
+            let crc = read_u32(entry, 4);
+            let offset = read_u64(entry, 8);
+            let len = read_u64(entry, 16);
+            let content = usize::try_from(offset)
+                .ok()
+                .zip(usize::try_from(len).ok())
+                .and_then(|(offset, len)| Some(offset..offset.checked_add(len)?))
+                .and_then(|range| bytes.get(range))
+                .ok_or_else(|| malformed(format!("section {kind} out of bounds")))?;
+            if verify && crc32fast::hash(content) != crc {
+                return Err(BinaryFormatError::ChecksumMismatch {
+                    section: format!("section {kind}"),
+                });
+            }
+            sections.push((kind, content));
+        }
+
+        Ok(Self {
+            float_width,
+            sections,
+        })
+    }
+
+    pub(crate) fn get(&self, kind: SectionKind) -> Result<&'a [u8], BinaryFormatError> {
+        self.sections
+            .iter()
+            .find(|(k, _)| *k == kind as u32)
+            .map(|&(_, content)| content)
+            .ok_or_else(|| BinaryFormatError::MissingSection {
+                section: format!("{kind:?}"),
+            })
+    }
+
+    fn u32_column(&self, kind: SectionKind) -> Result<Vec<u32>, BinaryFormatError> {
+        let content = self.get(kind)?;
+        if content.len() % 4 != 0 {
+            return Err(malformed(format!("{kind:?} is not an array of u32")));
+        }
+        Ok(content
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect())
+    }
+
+    fn optional_float_column<F: Float>(
+        &self,
+        kind: SectionKind,
+    ) -> Result<Option<Vec<F>>, BinaryFormatError> {
+        match self.float_column(kind) {
+            Ok(column) => Ok(Some(column)),
+            Err(BinaryFormatError::MissingSection { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn float_column<F: Float>(&self, kind: SectionKind) -> Result<Vec<F>, BinaryFormatError> {
+        let content = self.get(kind)?;
+        let width = self.float_width as usize;
+        if content.len() % width != 0 {
+            return Err(malformed(format!("{kind:?} is not an array of floats")));
+        }
+        Ok(content
+            .chunks_exact(width)
+            .map(|chunk| {
+                let v = if width == 4 {
+                    f32::from_le_bytes(chunk.try_into().unwrap()) as f64
+                } else {
+                    f64::from_le_bytes(chunk.try_into().unwrap())
+                };
+                F::from_f64(v)
+            })
+            .collect())
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// Check that `offsets` is a non-decreasing sequence starting at 0 and ending at `total`.
+pub(crate) fn check_offsets(
+    offsets: &[u32],
+    count: usize,
+    total: usize,
+    what: &str,
+) -> Result<(), BinaryFormatError> {
+    let valid = offsets.len() == count + 1
+        && offsets[0] == 0
+        && offsets[count] as usize == total
+        && offsets.windows(2).all(|w| w[0] <= w[1]);
+    if valid {
+        Ok(())
+    } else {
+        Err(malformed(format!("invalid {what} offsets")))
+    }
+}
+
+fn to_u32(v: usize, what: &str) -> Result<u32, BinaryFormatError> {
+    u32::try_from(v).map_err(|_| malformed(format!("{what} {v} does not fit in u32")))
+}
+
+#[derive(Default)]
+struct Columns {
+    forest_offsets: Vec<u32>,
+    base_values: Vec<f64>,
+    tree_offsets: Vec<u32>,
+    tree_roots: Vec<u32>,
+    node_ids: Vec<u32>,
+    node_split_indices: Vec<u32>,
+    node_split_conditions: Vec<f64>,
+    node_values: Vec<f64>,
+    node_lefts: Vec<u32>,
+    node_rights: Vec<u32>,
+    node_default_lefts: Vec<u8>,
+    node_covers: Vec<f64>,
+    node_internal_values: Vec<f64>,
+    node_gains: Vec<f64>,
+    node_hessians: Vec<f64>,
+}
+
+impl Columns {
+    fn from_model<F: Float>(model: &MultiOutputForest<F>) -> Result<Self, BinaryFormatError> {
+        let mut columns = Columns {
+            forest_offsets: vec![0],
+            tree_offsets: vec![0],
+            ..Default::default()
+        };
+        for forest in &model.forests {
+            columns.base_values.push(forest.base_value.into());
+            for tree in &forest.trees {
+                let position = |id: usize| {
+                    tree.node_map
+                        .get_index_of(&id)
+                        .ok_or_else(|| malformed(format!("dangling node id {id}")))
+                        .and_then(|pos| to_u32(pos, "node position"))
+                };
+                columns.tree_roots.push(position(tree.root)?);
+                for node in tree.node_map.values() {
+                    columns.node_ids.push(to_u32(node.id, "node id")?);
+                    columns
+                        .node_split_indices
+                        .push(to_u32(node.split_index, "split index")?);
+                    columns
+                        .node_split_conditions
+                        .push(node.split_condition.into_inner().into());
+                    columns.node_values.push(node.value.into_inner().into());
+                    columns
+                        .node_lefts
+                        .push(node.left.map_or(Ok(NO_CHILD), position)?);
+                    columns
+                        .node_rights
+                        .push(node.right.map_or(Ok(NO_CHILD), position)?);
+                    columns.node_default_lefts.push(match node.default_left {
+                        Some(false) => 0,
+                        Some(true) => 1,
+                        None => 2,
+                    });
+                    columns
+                        .node_covers
+                        .push(node.cover.map_or(f64::NAN, |c| c.into_inner().into()));
+                    columns.node_internal_values.push(
+                        node.internal_value
+                            .map_or(f64::NAN, |v| v.into_inner().into()),
+                    );
+                    columns
+                        .node_gains
+                        .push(node.gain.map_or(f64::NAN, |v| v.into_inner().into()));
+                    columns
+                        .node_hessians
+                        .push(node.hessian.map_or(f64::NAN, |v| v.into_inner().into()));
+                }
+                let node_count = columns.node_ids.len();
+                columns.tree_offsets.push(to_u32(node_count, "node count")?);
+            }
+            let tree_count = columns.tree_roots.len();
+            columns
+                .forest_offsets
+                .push(to_u32(tree_count, "tree count")?);
+        }
+        Ok(columns)
+    }
+
+    fn section(&self, kind: SectionKind, float_width: usize) -> Vec<u8> {
+        let u32s = |values: &[u32]| values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let floats = |values: &[f64]| {
+            values
+                .iter()
+                .flat_map(|&v| {
+                    if float_width == 4 {
+                        (v as f32).to_le_bytes().to_vec()
+                    } else {
+                        v.to_le_bytes().to_vec()
+                    }
+                })
+                .collect()
+        };
+        match kind {
+            SectionKind::ForestOffsets => u32s(&self.forest_offsets),
+            SectionKind::BaseValues => floats(&self.base_values),
+            SectionKind::TreeOffsets => u32s(&self.tree_offsets),
+            SectionKind::TreeRoots => u32s(&self.tree_roots),
+            SectionKind::NodeIds => u32s(&self.node_ids),
+            SectionKind::NodeSplitIndices => u32s(&self.node_split_indices),
+            SectionKind::NodeSplitConditions => floats(&self.node_split_conditions),
+            SectionKind::NodeValues => floats(&self.node_values),
+            SectionKind::NodeLefts => u32s(&self.node_lefts),
+            SectionKind::NodeRights => u32s(&self.node_rights),
+            SectionKind::NodeDefaultLefts => self.node_default_lefts.clone(),
+            SectionKind::NodeCovers => floats(&self.node_covers),
+            SectionKind::NodeInternalValues => floats(&self.node_internal_values),
+            SectionKind::NodeGains => floats(&self.node_gains),
+            SectionKind::NodeHessians => floats(&self.node_hessians),
+            SectionKind::Metadata => unreachable!("metadata is not a column"),
+        }
+    }
+}
+
+impl<F: Float> MultiOutputForest<F> {
+    /// Write the model in the compact binary format, see [`crate::binary`].
+    pub fn write_binary(&self, mut writer: impl Write) -> Result<(), BinaryFormatError> {
+        let float_width = size_of::<F>();
+        let columns = Columns::from_model(self)?;
+        let mut contents: Vec<(SectionKind, Vec<u8>)> = SectionKind::COLUMNS
+            .iter()
+            .map(|&kind| (kind, columns.section(kind, float_width)))
+            .collect();
+        let optional = [
+            (SectionKind::NodeCovers, &columns.node_covers),
+            (
+                SectionKind::NodeInternalValues,
+                &columns.node_internal_values,
+            ),
+            (SectionKind::NodeGains, &columns.node_gains),
+            (SectionKind::NodeHessians, &columns.node_hessians),
+        ];
+        for (kind, column) in optional {
+            if column.iter().any(|v| !v.is_nan()) {
+                contents.push((kind, columns.section(kind, float_width)));
+            }
+        }
+        if !self.metadata.is_empty() {
+            let metadata = serde_json::to_vec(&self.metadata).expect("metadata is valid JSON");
+            contents.push((SectionKind::Metadata, metadata));
+        }
+
+        let align = |offset: usize| offset.div_ceil(SECTION_ALIGN) * SECTION_ALIGN;
+        let mut table = Vec::with_capacity(contents.len() * SECTION_ENTRY_LEN);
+        let mut offset = align(HEADER_LEN + contents.len() * SECTION_ENTRY_LEN);
+        let mut offsets = Vec::with_capacity(contents.len());
+        for (kind, content) in &contents {
+            table.extend_from_slice(&(*kind as u32).to_le_bytes());
+            table.extend_from_slice(&crc32fast::hash(content).to_le_bytes());
+            table.extend_from_slice(&(offset as u64).to_le_bytes());
+            table.extend_from_slice(&(content.len() as u64).to_le_bytes());
+            offsets.push(offset);
+            offset = align(offset + content.len());
+        }
+
+        let mut bytes = Vec::with_capacity(offset);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(float_width as u32).to_le_bytes());
+        bytes.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&crc32fast::hash(&table).to_le_bytes());
+        bytes.extend_from_slice(&table);
+        for ((_, content), offset) in contents.iter().zip(offsets) {
+            bytes.resize(offset, 0);
+            bytes.extend_from_slice(content);
+        }
+
+        writer.write_all(&bytes)?;
+        Ok(())
+    }
+
+    /// Read a model written by [`MultiOutputForest::write_binary`].
+    ///
+    /// Models written with another float width are converted to `F`.
+    pub fn read_binary(mut reader: impl Read) -> Result<Self, BinaryFormatError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Self::from_binary_bytes(&bytes)
+    }
+
+    pub(crate) fn from_binary_bytes(bytes: &[u8]) -> Result<Self, BinaryFormatError> {
+        let sections = Sections::parse(bytes, true)?;
+
+        let forest_offsets = sections.u32_column(SectionKind::ForestOffsets)?;
+        let base_values: Vec<F> = sections.float_column(SectionKind::BaseValues)?;
+        let tree_offsets = sections.u32_column(SectionKind::TreeOffsets)?;
+        let tree_roots = sections.u32_column(SectionKind::TreeRoots)?;
+        let ids = sections.u32_column(SectionKind::NodeIds)?;
+        let split_indices = sections.u32_column(SectionKind::NodeSplitIndices)?;
+        let split_conditions: Vec<F> = sections.float_column(SectionKind::NodeSplitConditions)?;
+        let values: Vec<F> = sections.float_column(SectionKind::NodeValues)?;
+        let lefts = sections.u32_column(SectionKind::NodeLefts)?;
+        let rights = sections.u32_column(SectionKind::NodeRights)?;
+        let default_lefts = sections.get(SectionKind::NodeDefaultLefts)?;
+        let covers: Option<Vec<F>> = sections.optional_float_column(SectionKind::NodeCovers)?;
+        let internal_values: Option<Vec<F>> =
+            sections.optional_float_column(SectionKind::NodeInternalValues)?;
+        let gains: Option<Vec<F>> = sections.optional_float_column(SectionKind::NodeGains)?;
+        let hessians: Option<Vec<F>> = sections.optional_float_column(SectionKind::NodeHessians)?;
+
+        let n_trees = tree_roots.len();
+        let n_nodes = ids.len();
+        check_offsets(&forest_offsets, base_values.len(), n_trees, "forest")?;
+        check_offsets(&tree_offsets, n_trees, n_nodes, "tree")?;
+        let columns_match = [
+            split_indices.len(),
+            split_conditions.len(),
+            values.len(),
+            lefts.len(),
+            rights.len(),
+            default_lefts.len(),
+            covers.as_ref().map_or(n_nodes, Vec::len),
+            internal_values.as_ref().map_or(n_nodes, Vec::len),
+            gains.as_ref().map_or(n_nodes, Vec::len),
+            hessians.as_ref().map_or(n_nodes, Vec::len),
+        ]
+        .iter()
+        .all(|&len| len == n_nodes);
+        if !columns_match {
+            return Err(malformed("node columns have different lengths"));
+        }
+
+        let not_nan = |v: F| NotNan::new(v).map_err(|_| malformed("NaN in node values"));
+        let mut trees = Vec::with_capacity(n_trees);
+        for t in 0..n_trees {
+            let start = tree_offsets[t] as usize;
+            let end = tree_offsets[t + 1] as usize;
+            let tree_ids = &ids[start..end];
+            let node_id = |pos: u32| -> Result<Option<usize>, BinaryFormatError> {
+                if pos == NO_CHILD {
+                    Ok(None)
+                } else {
+                    tree_ids
+                        .get(pos as usize)
+                        .map(|&id| Some(id as usize))
+                        .ok_or_else(|| malformed(format!("child position {pos} out of bounds")))
+                }
+            };
+
+            let mut node_map = FxIndexMap::default();
+            for i in start..end {
+                let node = TreeNode {
+                    id: ids[i] as usize,
+                    split_index: split_indices[i] as usize,
+                    split_condition: not_nan(split_conditions[i])?,
+                    left: node_id(lefts[i])?,
+                    right: node_id(rights[i])?,
+                    value: not_nan(values[i])?,
+                    default_left: match default_lefts[i] {
+                        0 => Some(false),
+                        1 => Some(true),
+                        _ => None,
+                    },
+                    cover: covers.as_ref().and_then(|c| NotNan::new(c[i]).ok()),
+                    internal_value: internal_values
+                        .as_ref()
+                        .and_then(|v| NotNan::new(v[i]).ok()),
+                    gain: gains.as_ref().and_then(|v| NotNan::new(v[i]).ok()),
+                    hessian: hessians.as_ref().and_then(|v| NotNan::new(v[i]).ok()),
+                };
+                node_map.insert(node.id, node);
+            }
+            let root = node_id(tree_roots[t])?.ok_or_else(|| malformed("tree without root"))?;
+            trees.push(Tree::new(node_map, root));
+        }
+
+        let mut trees = trees.into_iter();
+        let forests = base_values
+            .into_iter()
+            .zip(forest_offsets.windows(2))
+            .map(|(base_value, w)| {
+                let trees = trees.by_ref().take((w[1] - w[0]) as usize).collect();
+                Forest::new(base_value, trees)
+            })
+            .collect();
+
+        let metadata = match sections.get(SectionKind::Metadata) {
+            Ok(content) => serde_json::from_slice(content)
+                .map_err(|e| malformed(format!("invalid metadata: {e}")))?,
+            Err(BinaryFormatError::MissingSection { .. }) => Default::default(),
+            Err(e) => return Err(e),
+        };
+
+        Ok(MultiOutputForest::new(forests).with_metadata(metadata))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::parser::{
+        read_lightgbm_model, read_xgboost_model,
+        test_utils::{read_features, test_model_prediction},
+    };
+
+    fn assert_same_model<F: Float, G: Float>(a: &MultiOutputForest<F>, b: &MultiOutputForest<G>) {
+        assert_eq!(
+            serde_json::to_value(a).unwrap(),
+            serde_json::to_value(b).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        for model_type in [
+            "regression",
+            "binary_classification",
+            "multiclass_classification",
+        ] {
+            let data_dir = root.join(format!("test_data/lightgbm/{model_type}"));
+            let model = read_lightgbm_model(data_dir.join("model.txt")).unwrap();
+            let mut bytes = Vec::new();
+            model.write_binary(&mut bytes).unwrap();
+            let loaded = MultiOutputForest::<f64>::read_binary(bytes.as_slice()).unwrap();
+            assert_same_model(&model, &loaded);
+            test_model_prediction(&data_dir, &loaded, 0.05).unwrap();
+        }
+
+        let data_dir = root.join("test_data/native/multiclass_classification");
+        let model = MultiOutputForest::<f64>::from_file(data_dir.join("model.json")).unwrap();
+        let mut bytes = Vec::new();
+        model.write_binary(&mut bytes).unwrap();
+        let loaded = MultiOutputForest::<f64>::read_binary(bytes.as_slice()).unwrap();
+        assert_same_model(&model, &loaded);
+    }
+
+    #[test]
+    fn test_float_width_conversion() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/xgboost/multiclass_classification");
+        let model = read_xgboost_model(data_dir.join("model.json")).unwrap();
+        let model_f32 = model.cast::<f32>();
+
+        let mut bytes = Vec::new();
+        model_f32.write_binary(&mut bytes).unwrap();
+        let loaded = MultiOutputForest::<f32>::read_binary(bytes.as_slice()).unwrap();
+        assert_same_model(&model_f32, &loaded);
+
+        // widening back to f64 is exact
+        let loaded = MultiOutputForest::<f64>::read_binary(bytes.as_slice()).unwrap();
+        for x in read_features(&data_dir.join("X.csv")) {
+            let x_f32: Vec<f32> = x.iter().map(|&v| v as f32).collect();
+            let expected: Vec<f64> = model_f32
+                .predict(&x_f32)
+                .iter()
+                .map(|v| v.into_inner() as f64)
+                .collect();
+            let actual: Vec<f64> = loaded.predict(&x).iter().map(|v| v.into_inner()).collect();
+            for (e, a) in expected.iter().zip(actual) {
+                assert!((e - a).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_corruption() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let model =
+            read_xgboost_model(root.join("test_data/xgboost/regression/model.json")).unwrap();
+        let mut bytes = Vec::new();
+        model.write_binary(&mut bytes).unwrap();
+
+        let mut corrupted = bytes.clone();
+        corrupted[0] = b'X';
+        assert!(matches!(
+            MultiOutputForest::<f64>::read_binary(corrupted.as_slice()),
+            Err(BinaryFormatError::InvalidMagic)
+        ));
+
+        let mut corrupted = bytes.clone();
+        corrupted[8..12].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            MultiOutputForest::<f64>::read_binary(corrupted.as_slice()),
+            Err(BinaryFormatError::UnsupportedVersion { .. })
+        ));
+
+        let mut corrupted = bytes.clone();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0xff;
+        assert!(matches!(
+            MultiOutputForest::<f64>::read_binary(corrupted.as_slice()),
+            Err(BinaryFormatError::ChecksumMismatch { .. })
+        ));
+
+        let truncated = &bytes[..bytes.len() / 2];
+        assert!(matches!(
+            MultiOutputForest::<f64>::read_binary(truncated),
+            Err(BinaryFormatError::Malformed { .. })
+        ));
+    }
+}
@@ -0,0 +1,493 @@
+use std::{fs::File, marker::PhantomData, ops::Range, path::Path};
+
+use memmap2::Mmap;
+use ordered_float::NotNan;
+
+use super::{BinaryFormatError, NO_CHILD, SectionKind, Sections, check_offsets, malformed};
+use crate::{Error, Float, error::malformed_tree};
+
+fn zero_copy(message: impl Into<String>) -> BinaryFormatError {
+    BinaryFormatError::ZeroCopy {
+        message: message.into(),
+    }
+}
+
+/// A [`MultiOutputForest`](crate::MultiOutputForest) evaluated in place on the bytes of a binary
+/// model, without deserialization.
+///
+/// The bytes must start at an 8-byte aligned address, which memory maps always do, and the float
+/// width of the file must match `F`.
+#[derive(Debug, Clone, Copy)]
+pub struct MultiOutputForestView<'a, F: Float = f64> {
+    forest_offsets: &'a [u32],
+    base_values: &'a [F],
+    tree_offsets: &'a [u32],
+    tree_roots: &'a [u32],
+    split_indices: &'a [u32],
+    split_conditions: &'a [F],
+    values: &'a [F],
+    lefts: &'a [u32],
+    rights: &'a [u32],
+    default_lefts: &'a [u8],
+}
+
+impl<'a, F: Float> MultiOutputForestView<'a, F> {
+    /// View the binary model in `bytes`, validating checksums and tree structure.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, BinaryFormatError> {
+        let view = Self::parse(bytes, true)?;
+        view.validate_layout()?;
+        view.validate_nodes()?;
+        Ok(view)
+    }
+
+    fn parse(bytes: &'a [u8], verify: bool) -> Result<Self, BinaryFormatError> {
+        if cfg!(target_endian = "big") {
+            return Err(zero_copy("arrays are little-endian"));
+        }
+        let sections = Sections::parse(bytes, verify)?;
+        if sections.float_width as usize != size_of::<F>() {
+            return Err(zero_copy(format!(
+                "model has {}-byte floats, expected {}",
+                sections.float_width,
+                size_of::<F>()
+            )));
+        }
+
+        fn cast<'a, T: bytemuck::Pod>(
+            sections: &Sections<'a>,
+            kind: SectionKind,
+        ) -> Result<&'a [T], BinaryFormatError> {
+            bytemuck::try_cast_slice(sections.get(kind)?).map_err(|e| match e {
+                bytemuck::PodCastError::TargetAlignmentGreaterAndInputNotAligned => {
+                    zero_copy("bytes are not 8-byte aligned")
+                }
+                _ => malformed(format!("{kind:?} has an invalid length")),
+            })
+        }
+
+        Ok(Self {
+            forest_offsets: cast(&sections, SectionKind::ForestOffsets)?,
+            base_values: cast(&sections, SectionKind::BaseValues)?,
+            tree_offsets: cast(&sections, SectionKind::TreeOffsets)?,
+            tree_roots: cast(&sections, SectionKind::TreeRoots)?,
+            split_indices: cast(&sections, SectionKind::NodeSplitIndices)?,
+            split_conditions: cast(&sections, SectionKind::NodeSplitConditions)?,
+            values: cast(&sections, SectionKind::NodeValues)?,
+            lefts: cast(&sections, SectionKind::NodeLefts)?,
+            rights: cast(&sections, SectionKind::NodeRights)?,
+            default_lefts: sections.get(SectionKind::NodeDefaultLefts)?,
+        })
+    }
+
+    /// Check the forest and tree offsets and the lengths of the node columns, in time
+    /// proportional to the number of trees.
+    fn validate_layout(&self) -> Result<(), BinaryFormatError> {
+        let n_trees = self.tree_roots.len();
+        let n_nodes = self.split_indices.len();
+        check_offsets(
+            self.forest_offsets,
+            self.base_values.len(),
+            n_trees,
+            "forest",
+        )?;
+        check_offsets(self.tree_offsets, n_trees, n_nodes, "tree")?;
+        let columns_match = [
+            self.split_conditions.len(),
+            self.values.len(),
+            self.lefts.len(),
+            self.rights.len(),
+            self.default_lefts.len(),
+        ]
+        .iter()
+        .all(|&len| len == n_nodes);
+        if !columns_match {
+            return Err(malformed("node columns have different lengths"));
+        }
+        Ok(())
+    }
+
+    /// Check every node, so that [`Self::predict`] cannot index out of bounds, meet a NaN or loop:
+    /// every node must be reached at most once from the root of its tree.
+    fn validate_nodes(&self) -> Result<(), BinaryFormatError> {
+        if self.split_conditions.iter().any(|v| v.is_nan())
+            || self.values.iter().any(|v| v.is_nan())
+        {
+            return Err(malformed("NaN in node values"));
+        }
+
+        let mut reached = Vec::new();
+        let mut stack = Vec::new();
+        for (t, w) in self.tree_offsets.windows(2).enumerate() {
+            let len = w[1] - w[0];
+            if self.tree_roots[t] >= len {
+                return Err(malformed(format!("root of tree {t} out of bounds")));
+            }
+            for i in w[0] as usize..w[1] as usize {
+                match (self.lefts[i], self.rights[i]) {
+                    (NO_CHILD, NO_CHILD) => {}
+                    (left, right) if left < len && right < len => {}
+                    _ => return Err(malformed(format!("invalid children in tree {t}"))),
+                }
+            }
+
+            reached.clear();
+            reached.resize(len as usize, false);
+            stack.push(self.tree_roots[t]);
+            while let Some(node) = stack.pop() {
+                if std::mem::replace(&mut reached[node as usize], true) {
+                    return Err(malformed(format!(
+                        "node {node} of tree {t} is reached twice"
+                    )));
+                }
+                let i = (w[0] + node) as usize;
+                if self.lefts[i] != NO_CHILD {
+                    stack.extend([self.lefts[i], self.rights[i]]);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn n_outputs(&self) -> usize {
+        self.base_values.len()
+    }
+
+    pub fn n_trees(&self) -> usize {
+        self.tree_roots.len()
+    }
+
+    /// Output of a tree for `x`, checking what [`Self::validate_nodes`] checks on the way, since
+    /// views of an unverified [`MappedModel`] skip it.
+    fn try_predict_tree(&self, tree: usize, x: &[F]) -> Result<F, Error> {
+        let start = self.tree_offsets[tree] as usize;
+        let len = self.tree_offsets[tree + 1] as usize - start;
+        let mut node = self.tree_roots[tree] as usize;
+        // a valid path visits every node at most once
+        for _ in 0..len {
+            let i = start + node;
+            if self.lefts[i] == NO_CHILD {
+                return Ok(self.values[i]);
+            }
+            let split_index = self.split_indices[i] as usize;
+            let feature = *x.get(split_index).ok_or(Error::FeatureIndex {
+                feature: split_index,
+                node,
+                actual: x.len(),
+            })?;
+            let go_left = if feature.is_nan() {
+                match self.default_lefts[i] {
+                    0 => false,
+                    1 => true,
+                    _ => {
+                        return Err(Error::MissingValue {
+                            feature: split_index,
+                            node,
+                        });
+                    }
+                }
+            } else {
+                feature < self.split_conditions[i]
+            };
+            let next = if go_left {
+                self.lefts[i]
+            } else {
+                self.rights[i]
+            };
+            node = next as usize;
+            if node >= len {
+                return Err(malformed_tree(format!("node {next} does not exist")));
+            }
+        }
+        Err(malformed_tree("cycle in tree"))
+    }
+
+    /// Same as [`MultiOutputForest::try_predict`](crate::MultiOutputForest::try_predict), without
+    /// the feature count check since the view does not read the metadata.
+    pub fn try_predict(&self, x: &[F]) -> Result<Vec<NotNan<F>>, Error> {
+        self.base_values
+            .iter()
+            .zip(self.forest_offsets.windows(2))
+            .map(|(&base_value, w)| {
+                let predictions = (w[0] as usize..w[1] as usize)
+                    .map(|tree| self.try_predict_tree(tree, x))
+                    .collect::<Result<Vec<F>, _>>()?;
+                let res = base_value + predictions.iter().copied().sum::<F>();
+                NotNan::new(res)
+                    .ok()
+                    .filter(|res| res.is_finite())
+                    .ok_or(Error::NonFiniteOutput)
+            })
+            .collect()
+    }
+
+    fn predict_tree(&self, tree: usize, x: &[F]) -> F {
+        self.try_predict_tree(tree, x)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Same as [`MultiOutputForest::predict`](crate::MultiOutputForest::predict).
+    pub fn predict(&self, x: &[F]) -> Vec<NotNan<F>> {
+        self.base_values
+            .iter()
+            .zip(self.forest_offsets.windows(2))
+            .map(|(&base_value, w)| {
+                let predictions: Vec<F> = (w[0] as usize..w[1] as usize)
+                    .map(|tree| self.predict_tree(tree, x))
+                    .collect();
+                let res = base_value + predictions.iter().copied().sum::<F>();
+                NotNan::new(res).unwrap()
+            })
+            .collect()
+    }
+}
+
+/// Byte ranges of the arrays of a [`MultiOutputForestView`] within the bytes it views.
+#[derive(Debug, Clone)]
+struct Layout {
+    forest_offsets: Range<usize>,
+    base_values: Range<usize>,
+    tree_offsets: Range<usize>,
+    tree_roots: Range<usize>,
+    split_indices: Range<usize>,
+    split_conditions: Range<usize>,
+    values: Range<usize>,
+    lefts: Range<usize>,
+    rights: Range<usize>,
+    default_lefts: Range<usize>,
+}
+
+impl Layout {
+    fn of<F: Float>(view: &MultiOutputForestView<'_, F>, bytes: &[u8]) -> Self {
+        let base = bytes.as_ptr() as usize;
+        fn range<T>(slice: &[T], base: usize) -> Range<usize> {
+            let start = slice.as_ptr() as usize - base;
+            start..start + size_of_val(slice)
+        }
+        Self {
+            forest_offsets: range(view.forest_offsets, base),
+            base_values: range(view.base_values, base),
+            tree_offsets: range(view.tree_offsets, base),
+            tree_roots: range(view.tree_roots, base),
+            split_indices: range(view.split_indices, base),
+            split_conditions: range(view.split_conditions, base),
+            values: range(view.values, base),
+            lefts: range(view.lefts, base),
+            rights: range(view.rights, base),
+            default_lefts: range(view.default_lefts, base),
+        }
+    }
+
+    /// The view of `bytes`, which must be the bytes the layout was taken from.
+    fn view<'a, F: Float>(&self, bytes: &'a [u8]) -> MultiOutputForestView<'a, F> {
+        MultiOutputForestView {
+            forest_offsets: bytemuck::cast_slice(&bytes[self.forest_offsets.clone()]),
+            base_values: bytemuck::cast_slice(&bytes[self.base_values.clone()]),
+            tree_offsets: bytemuck::cast_slice(&bytes[self.tree_offsets.clone()]),
+            tree_roots: bytemuck::cast_slice(&bytes[self.tree_roots.clone()]),
+            split_indices: bytemuck::cast_slice(&bytes[self.split_indices.clone()]),
+            split_conditions: bytemuck::cast_slice(&bytes[self.split_conditions.clone()]),
+            values: bytemuck::cast_slice(&bytes[self.values.clone()]),
+            lefts: bytemuck::cast_slice(&bytes[self.lefts.clone()]),
+            rights: bytemuck::cast_slice(&bytes[self.rights.clone()]),
+            default_lefts: &bytes[self.default_lefts.clone()],
+        }
+    }
+}
+
+/// A memory-mapped binary model, shared through the page cache by every process mapping the
+/// same file.
+#[derive(Debug)]
+pub struct MappedModel<F: Float = f64> {
+    mmap: Mmap,
+    layout: Layout,
+    _float: PhantomData<F>,
+}
+
+impl<F: Float> MappedModel<F> {
+    /// Map the binary model at `path`, reading only its header, section table and tree offsets,
+    /// so that opening takes the same time whatever the size of the model.
+    ///
+    /// Checksums and nodes are not verified: a corrupted file may then give wrong predictions or
+    /// panic. Use [`Self::open_verified`] for files that may be damaged or untrusted. The file
+    /// must not be modified while it is mapped.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, BinaryFormatError> {
+        Self::map(path, false)
+    }
+
+    /// Same as [`Self::open`], but also verifies the checksums of every section and the structure
+    /// of every tree, reading the whole file once.
+    pub fn open_verified(path: impl AsRef<Path>) -> Result<Self, BinaryFormatError> {
+        Self::map(path, true)
+    }
+
+    fn map(path: impl AsRef<Path>, verify: bool) -> Result<Self, BinaryFormatError> {
+        let file = File::open(path)?;
+        // SAFETY: the mapping is read-only, and models are not expected to be modified in place
+        // while being served; this is the documented contract of `open`.
+        let mmap = unsafe { Mmap::map(&file)? };
+        let view = MultiOutputForestView::<F>::parse(&mmap, verify)?;
+        view.validate_layout()?;
+        if verify {
+            view.validate_nodes()?;
+        }
+        let layout = Layout::of(&view, &mmap);
+        Ok(Self {
+            mmap,
+            layout,
+            _float: PhantomData,
+        })
+    }
+
+    pub fn view(&self) -> MultiOutputForestView<'_, F> {
+        self.layout.view(&self.mmap)
+    }
+
+    pub fn predict(&self, x: &[F]) -> Vec<NotNan<F>> {
+        self.view().predict(x)
+    }
+
+    pub fn try_predict(&self, x: &[F]) -> Result<Vec<NotNan<F>>, Error> {
+        self.view().try_predict(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{
+        Forest, MultiOutputForest, Tree, TreeNode,
+        parser::{read_lightgbm_model, read_xgboost_model, test_utils::read_features},
+    };
+
+    /// Copy `bytes` into an 8-byte aligned buffer.
+    fn aligned(bytes: &[u8]) -> Vec<u64> {
+        let mut buffer = vec![0u64; bytes.len().div_ceil(8)];
+        bytemuck::cast_slice_mut::<u64, u8>(&mut buffer)[..bytes.len()].copy_from_slice(bytes);
+        buffer
+    }
+
+    fn binary<F: Float>(model: &MultiOutputForest<F>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        model.write_binary(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_view_predict() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/xgboost/multiclass_classification");
+        let model = read_xgboost_model(data_dir.join("model.json")).unwrap();
+        let model_f32 = model.cast::<f32>();
+        let bytes = binary(&model);
+        let buffer = aligned(&bytes);
+        let view = MultiOutputForestView::<f64>::from_bytes(bytemuck::cast_slice(&buffer)).unwrap();
+        let buffer_f32 = aligned(&binary(&model_f32));
+        let view_f32 =
+            MultiOutputForestView::<f32>::from_bytes(bytemuck::cast_slice(&buffer_f32)).unwrap();
+        assert_eq!(view.n_outputs(), model.forests.len());
+
+        for x in read_features(&data_dir.join("X.csv")) {
+            assert_eq!(view.predict(&x), model.predict(&x));
+            assert_eq!(view.try_predict(&x).unwrap(), model.predict(&x));
+            let x_f32: Vec<f32> = x.iter().map(|&v| v as f32).collect();
+            assert_eq!(view_f32.predict(&x_f32), model_f32.predict(&x_f32));
+        }
+    }
+
+    #[test]
+    fn test_mapped_model() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/lightgbm/multiclass_classification");
+        let model = read_lightgbm_model(data_dir.join("model.txt")).unwrap();
+
+        let path = std::env::temp_dir().join(format!("silva-mmap-{}.bin", std::process::id()));
+        std::fs::write(&path, binary(&model)).unwrap();
+        let mapped = MappedModel::<f64>::open(&path).unwrap();
+        let verified = MappedModel::<f64>::open_verified(&path).unwrap();
+        for x in read_features(&data_dir.join("X.csv")) {
+            assert_eq!(mapped.predict(&x), model.predict(&x));
+            assert_eq!(verified.predict(&x), model.predict(&x));
+        }
+        drop(verified);
+        assert!(matches!(
+            MappedModel::<f32>::open(&path),
+            Err(BinaryFormatError::ZeroCopy { .. })
+        ));
+
+        // corrupt a leaf value: only the verified open reads far enough to notice
+        let mut bytes = std::fs::read(&path).unwrap();
+        let values = mapped.layout.values.clone();
+        let leaf = (0..mapped.view().values.len())
+            .find(|&i| mapped.view().lefts[i] == NO_CHILD)
+            .unwrap();
+        let offset = values.start + leaf * size_of::<f64>();
+        bytes[offset..offset + 8].copy_from_slice(&1234.5f64.to_le_bytes());
+        drop(mapped);
+        std::fs::write(&path, &bytes).unwrap();
+        let mapped = MappedModel::<f64>::open(&path).unwrap();
+        assert_eq!(mapped.view().values[leaf], 1234.5);
+        assert!(matches!(
+            MappedModel::<f64>::open_verified(&path),
+            Err(BinaryFormatError::ChecksumMismatch { .. })
+        ));
+        drop(mapped);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_misaligned() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let model =
+            read_xgboost_model(root.join("test_data/xgboost/regression/model.json")).unwrap();
+        let bytes = binary(&model);
+        let mut buffer = aligned(&[&[0u8][..], &bytes].concat());
+        let shifted = &bytemuck::cast_slice_mut::<u64, u8>(&mut buffer)[1..=bytes.len()];
+        assert!(matches!(
+            MultiOutputForestView::<f64>::from_bytes(shifted),
+            Err(BinaryFormatError::ZeroCopy { .. })
+        ));
+    }
+
+    #[test]
+    fn test_view_try_predict() {
+        // the split lists its parent as its left child, and has no default direction
+        let tree = Tree::from_nodes(vec![
+            TreeNode::split(0, 0, 1.0, 1, 2),
+            TreeNode::split(1, 1, 1.0, 0, 2),
+            TreeNode::leaf(2, 2.0),
+        ]);
+        let model = MultiOutputForest::new(vec![Forest::new(0.0, vec![tree])]);
+        let buffer = aligned(&binary(&model));
+        let bytes = bytemuck::cast_slice(&buffer);
+        assert!(matches!(
+            MultiOutputForestView::<f64>::from_bytes(bytes),
+            Err(BinaryFormatError::Malformed { .. })
+        ));
+
+        // without node validation, as for an unverified mapped model
+        let view = MultiOutputForestView::<f64>::parse(bytes, true).unwrap();
+        view.validate_layout().unwrap();
+        assert_eq!(view.try_predict(&[2.0, 0.0]).unwrap(), [2.0]);
+        assert!(matches!(
+            view.try_predict(&[0.0, 0.0]),
+            Err(Error::MalformedTree { .. })
+        ));
+        assert!(matches!(
+            view.try_predict(&[f64::NAN, 0.0]),
+            Err(Error::MissingValue {
+                feature: 0,
+                node: 0
+            })
+        ));
+        assert!(matches!(
+            view.try_predict(&[0.0]),
+            Err(Error::FeatureIndex {
+                feature: 1,
+                node: 1,
+                actual: 1
+            })
+        ));
+    }
+}
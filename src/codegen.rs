@@ -0,0 +1,110 @@
+mod c;
+mod rust;
+
+pub use c::{CCodegenOptions, CFloatType, CSource, generate_c_source};
+pub use rust::{RustCodegenOptions, generate_rust_module, write_rust_module};
+
+use crate::{Float, Tree};
+
+/// How the trees of a model are laid out in generated source code.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CodegenStyle {
+    /// One function per tree made of nested `if`/`else` branches.
+    #[default]
+    Branches,
+    /// A single flat table of nodes shared by all trees, walked by a small loop.
+    NodeTable,
+}
+
+/// A tree node with children referenced by position in a flat node table.
+pub(crate) struct FlatNode<F: Float> {
+    pub(crate) split_index: usize,
+    /// Split threshold for internal nodes, leaf value for leaves.
+    pub(crate) value: F,
+    /// `None` for leaves.
+    pub(crate) children: Option<(usize, usize)>,
+    /// Whether missing (NaN) values go to the left child.
+    pub(crate) default_left: bool,
+}
+
+/// Append the nodes of `tree` to `table` in pre-order and return the position of its root.
+pub(crate) fn flatten_tree<F: Float>(tree: &Tree<F>, table: &mut Vec<FlatNode<F>>) -> usize {
+    fn visit<F: Float>(tree: &Tree<F>, id: usize, table: &mut Vec<FlatNode<F>>) -> usize {
+        let node = &tree.node_map[&id];
+        let pos = table.len();
+        match (node.left, node.right) {
+            (Some(left), Some(right)) => {
+                table.push(FlatNode {
+                    split_index: node.split_index,
+                    value: node.split_condition.into_inner(),
+                    children: None,
+                    default_left: node.default_left.unwrap_or(false),
+                });
+                let left_pos = visit(tree, left, table);
+                let right_pos = visit(tree, right, table);
+                table[pos].children = Some((left_pos, right_pos));
+            }
+            _ => table.push(FlatNode {
+                split_index: 0,
+                value: node.value.into_inner(),
+                children: None,
+                default_left: false,
+            }),
+        }
+        pos
+    }
+
+    visit(tree, tree.root, table)
+}
+
+#[cfg(test)]
+mod test_utils {
+    use std::{fs, path::Path};
+
+    use crate::{Float, MultiOutputForest};
+
+    /// Copy of `rows` where every row has one feature replaced by NaN.
+    pub fn with_missing_values(rows: &[Vec<f64>]) -> Vec<Vec<f64>> {
+        rows.iter()
+            .enumerate()
+            .map(|(i, row)| {
+                let mut row = row.clone();
+                let j = i % row.len();
+                row[j] = f64::NAN;
+                row
+            })
+            .collect()
+    }
+
+    pub fn write_rows(path: &Path, rows: &[Vec<f64>]) {
+        let content: String = rows
+            .iter()
+            .map(|row| {
+                let line: Vec<String> = row.iter().map(|v| format!("{v:?}")).collect();
+                line.join(",") + "\n"
+            })
+            .collect();
+        fs::write(path, content).unwrap();
+    }
+
+    pub fn parse_outputs(stdout: &[u8]) -> Vec<f64> {
+        String::from_utf8_lossy(stdout)
+            .lines()
+            .flat_map(|line| {
+                line.split(',')
+                    .map(|s| s.parse::<f64>().unwrap())
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    pub fn expected_outputs<F: Float>(model: &MultiOutputForest<F>, rows: &[Vec<f64>]) -> Vec<f64> {
+        rows.iter()
+            .flat_map(|x| {
+                let x: Vec<F> = x.iter().map(|&v| F::from_f64(v)).collect();
+                model.predict(&x)
+            })
+            .map(|v| v.into_inner().into())
+            .collect()
+    }
+}
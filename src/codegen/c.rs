@@ -0,0 +1,528 @@
+use std::fmt::{self, Write};
+
+use crate::{Float, MultiOutputForest, Tree};
+
+use super::{CodegenStyle, flatten_tree};
+
+/// Floating point type used by generated C code for inputs, thresholds and outputs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CFloatType {
+    Float,
+    #[default]
+    Double,
+}
+
+impl CFloatType {
+    fn name(self) -> &'static str {
+        match self {
+            CFloatType::Float => "float",
+            CFloatType::Double => "double",
+        }
+    }
+
+    fn literal<F: Float>(self, v: F) -> String {
+        let v: f64 = v.into();
+        if v.is_infinite() {
+            let sign = if v < 0.0 { "-" } else { "" };
+            return format!("{sign}INFINITY");
+        }
+        // `Debug` prints the shortest representation that round-trips,
+        // and always includes a `.` or an exponent
+        match self {
+            CFloatType::Float => format!("{:?}f", v as f32),
+            CFloatType::Double => format!("{v:?}"),
+        }
+    }
+}
+
+/// Options for [`generate_c_source`].
+#[derive(Debug, Clone)]
+pub struct CCodegenOptions {
+    pub style: CodegenStyle,
+    pub float_type: CFloatType,
+    /// Route missing (NaN) values to the default direction of each split. When disabled,
+    /// NaN inputs always go right, which saves a check per split.
+    pub handle_missing: bool,
+    /// Prefix of every exported symbol and of the header file name.
+    pub prefix: String,
+}
+
+impl Default for CCodegenOptions {
+    fn default() -> Self {
+        Self {
+            style: CodegenStyle::default(),
+            float_type: CFloatType::default(),
+            handle_missing: true,
+            prefix: "silva".to_string(),
+        }
+    }
+}
+
+/// A generated C99 header and source file pair.
+#[derive(Debug, Clone)]
+pub struct CSource {
+    /// File name the source expects the header to have, i.e. `<prefix>.h`.
+    pub header_name: String,
+    pub header: String,
+    pub source: String,
+}
+
+/// Generate a self-contained C99 `.h`/`.c` pair that evaluates `model`.
+///
+/// The header declares `void <prefix>_predict(const T *x, T *out)`, which writes one raw
+/// value per output into `out`, and defines `<PREFIX>_N_OUTPUTS`. The source only depends
+/// on `<math.h>` and `<stdint.h>`.
+pub fn generate_c_source<F: Float>(
+    model: &MultiOutputForest<F>,
+    options: &CCodegenOptions,
+) -> CSource {
+    let mut header = String::new();
+    let mut source = String::new();
+    write_header(&mut header, model, options).expect("writing to a String cannot fail");
+    write_source(&mut source, model, options).expect("writing to a String cannot fail");
+    CSource {
+        header_name: format!("{}.h", options.prefix),
+        header,
+        source,
+    }
+}
+
+fn write_header<F: Float>(
+    out: &mut impl Write,
+    model: &MultiOutputForest<F>,
+    options: &CCodegenOptions,
+) -> fmt::Result {
+    let prefix = &options.prefix;
+    let macro_prefix = prefix.to_uppercase();
+    let float = options.float_type.name();
+
+    writeln!(out, "/* @generated by silva. Do not edit by hand. */")?;
+    writeln!(out, "#ifndef {macro_prefix}_H")?;
+    writeln!(out, "#define {macro_prefix}_H")?;
+    writeln!(out)?;
+    writeln!(out, "#ifdef __cplusplus")?;
+    writeln!(out, "extern \"C\" {{")?;
+    writeln!(out, "#endif")?;
+    writeln!(out)?;
+    writeln!(out, "/* Number of values written by {prefix}_predict. */")?;
+    writeln!(
+        out,
+        "#define {macro_prefix}_N_OUTPUTS {}",
+        model.forests.len()
+    )?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "/* Raw (untransformed) prediction, one value per output. */"
+    )?;
+    writeln!(
+        out,
+        "void {prefix}_predict(const {float} *x, {float} *out);"
+    )?;
+    writeln!(out)?;
+    writeln!(out, "#ifdef __cplusplus")?;
+    writeln!(out, "}}")?;
+    writeln!(out, "#endif")?;
+    writeln!(out)?;
+    writeln!(out, "#endif /* {macro_prefix}_H */")
+}
+
+fn write_source<F: Float>(
+    out: &mut impl Write,
+    model: &MultiOutputForest<F>,
+    options: &CCodegenOptions,
+) -> fmt::Result {
+    writeln!(out, "/* @generated by silva. Do not edit by hand. */")?;
+    writeln!(out, "#include <math.h>")?;
+    writeln!(out, "#include <stdint.h>")?;
+    writeln!(out)?;
+    writeln!(out, "#include \"{}.h\"", options.prefix)?;
+    writeln!(out)?;
+
+    match options.style {
+        CodegenStyle::Branches => write_branches(out, model, options),
+        CodegenStyle::NodeTable => write_node_table(out, model, options),
+    }
+}
+
+fn write_branches<F: Float>(
+    out: &mut impl Write,
+    model: &MultiOutputForest<F>,
+    options: &CCodegenOptions,
+) -> fmt::Result {
+    let prefix = &options.prefix;
+    let float = options.float_type.name();
+
+    for (i, forest) in model.forests.iter().enumerate() {
+        for (j, tree) in forest.trees.iter().enumerate() {
+            writeln!(
+                out,
+                "static {float} {prefix}_tree_{i}_{j}(const {float} *x) {{"
+            )?;
+            write_node(out, tree, tree.root, 1, options)?;
+            writeln!(out, "}}")?;
+            writeln!(out)?;
+        }
+    }
+
+    writeln!(
+        out,
+        "void {prefix}_predict(const {float} *x, {float} *out) {{"
+    )?;
+    for (i, forest) in model.forests.iter().enumerate() {
+        write!(
+            out,
+            "    out[{i}] = {}",
+            options.float_type.literal(forest.base_value)
+        )?;
+        if !forest.trees.is_empty() {
+            let calls = (0..forest.trees.len())
+                .map(|j| format!("{prefix}_tree_{i}_{j}(x)"))
+                .collect::<Vec<_>>()
+                .join(" + ");
+            // `Forest::predict` sums the trees first and adds the base value last
+            write!(out, " + ({calls})")?;
+        }
+        writeln!(out, ";")?;
+    }
+    writeln!(out, "}}")
+}
+
+fn write_node<F: Float>(
+    out: &mut impl Write,
+    tree: &Tree<F>,
+    id: usize,
+    depth: usize,
+    options: &CCodegenOptions,
+) -> fmt::Result {
+    let indent = "    ".repeat(depth);
+    let node = &tree.node_map[&id];
+    match (node.left, node.right) {
+        (Some(left), Some(right)) => {
+            let i = node.split_index;
+            let threshold = options
+                .float_type
+                .literal(node.split_condition.into_inner());
+            // NaN compares false, so only default-left splits need an explicit check
+            if options.handle_missing && node.default_left == Some(true) {
+                writeln!(out, "{indent}if (x[{i}] < {threshold} || isnan(x[{i}])) {{")?;
+            } else {
+                writeln!(out, "{indent}if (x[{i}] < {threshold}) {{")?;
+            }
+            write_node(out, tree, left, depth + 1, options)?;
+            writeln!(out, "{indent}}} else {{")?;
+            write_node(out, tree, right, depth + 1, options)?;
+            writeln!(out, "{indent}}}")
+        }
+        _ => writeln!(
+            out,
+            "{indent}return {};",
+            options.float_type.literal(node.value.into_inner())
+        ),
+    }
+}
+
+fn write_node_table<F: Float>(
+    out: &mut impl Write,
+    model: &MultiOutputForest<F>,
+    options: &CCodegenOptions,
+) -> fmt::Result {
+    let prefix = &options.prefix;
+    let macro_prefix = prefix.to_uppercase();
+    let float = options.float_type.name();
+
+    let mut table = Vec::new();
+    let roots: Vec<Vec<usize>> = model
+        .forests
+        .iter()
+        .map(|forest| {
+            forest
+                .trees
+                .iter()
+                .map(|tree| flatten_tree(tree, &mut table))
+                .collect()
+        })
+        .collect();
+
+    writeln!(out, "#define {macro_prefix}_LEAF UINT32_MAX")?;
+    writeln!(out)?;
+    writeln!(out, "typedef struct {{")?;
+    writeln!(
+        out,
+        "    {float} value; /* split threshold, or leaf value for leaves */"
+    )?;
+    writeln!(out, "    uint32_t split_index;")?;
+    writeln!(out, "    uint32_t left;")?;
+    writeln!(out, "    uint32_t right;")?;
+    if options.handle_missing {
+        writeln!(out, "    uint8_t default_left;")?;
+    }
+    writeln!(out, "}} {prefix}_node;")?;
+    writeln!(out)?;
+
+    writeln!(
+        out,
+        "static const {prefix}_node {prefix}_nodes[{}] = {{",
+        table.len()
+    )?;
+    for node in &table {
+        let (left, right) = match node.children {
+            Some((left, right)) => (left.to_string(), right.to_string()),
+            None => (
+                format!("{macro_prefix}_LEAF"),
+                format!("{macro_prefix}_LEAF"),
+            ),
+        };
+        write!(
+            out,
+            "    {{{}, {}, {left}, {right}",
+            options.float_type.literal(node.value),
+            node.split_index
+        )?;
+        if options.handle_missing {
+            write!(out, ", {}", u8::from(node.default_left))?;
+        }
+        writeln!(out, "}},")?;
+    }
+    writeln!(out, "}};")?;
+    writeln!(out)?;
+
+    // roots of all outputs concatenated, output i owns roots[offsets[i]..offsets[i + 1]]
+    let all_roots = roots
+        .iter()
+        .flatten()
+        .map(|pos| pos.to_string())
+        .collect::<Vec<_>>();
+    let mut offsets = vec![0];
+    for output_roots in &roots {
+        offsets.push(offsets.last().unwrap() + output_roots.len());
+    }
+    writeln!(
+        out,
+        "static const uint32_t {prefix}_roots[{}] = {{{}}};",
+        all_roots.len().max(1),
+        if all_roots.is_empty() {
+            "0".to_string()
+        } else {
+            all_roots.join(", ")
+        }
+    )?;
+    writeln!(
+        out,
+        "static const uint32_t {prefix}_root_offsets[{}] = {{{}}};",
+        offsets.len(),
+        offsets
+            .iter()
+            .map(|o| o.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    )?;
+    writeln!(
+        out,
+        "static const {float} {prefix}_base_values[{}] = {{{}}};",
+        model.forests.len(),
+        model
+            .forests
+            .iter()
+            .map(|forest| options.float_type.literal(forest.base_value))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )?;
+    writeln!(out)?;
+
+    let go_left = if options.handle_missing {
+        "v < node->value || (node->default_left && isnan(v))"
+    } else {
+        "v < node->value"
+    };
+    writeln!(
+        out,
+        "void {prefix}_predict(const {float} *x, {float} *out) {{"
+    )?;
+    writeln!(out, "    uint32_t i, r;")?;
+    writeln!(out, "    for (i = 0; i < {macro_prefix}_N_OUTPUTS; i++) {{")?;
+    writeln!(out, "        {float} sum = 0;")?;
+    writeln!(
+        out,
+        "        for (r = {prefix}_root_offsets[i]; r < {prefix}_root_offsets[i + 1]; r++) {{"
+    )?;
+    writeln!(
+        out,
+        "            const {prefix}_node *node = &{prefix}_nodes[{prefix}_roots[r]];"
+    )?;
+    writeln!(
+        out,
+        "            while (node->left != {macro_prefix}_LEAF) {{"
+    )?;
+    writeln!(out, "                {float} v = x[node->split_index];")?;
+    writeln!(
+        out,
+        "                node = &{prefix}_nodes[({go_left}) ? node->left : node->right];"
+    )?;
+    writeln!(out, "            }}")?;
+    writeln!(out, "            sum += node->value;")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "        out[i] = {prefix}_base_values[i] + sum;")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf, process::Command};
+
+    use super::*;
+    use crate::{
+        codegen::test_utils::{expected_outputs, parse_outputs, with_missing_values, write_rows},
+        parser::{
+            read_lightgbm_model, read_xgboost_model,
+            test_utils::{all_close, read_features},
+        },
+    };
+
+    const HARNESS: &str = r#"
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "model.h"
+
+int main(int argc, char **argv) {
+    char line[65536];
+    FLOAT x[1024];
+    FLOAT out[MODEL_N_OUTPUTS];
+    FILE *f;
+    int i;
+    if (argc != 2 || (f = fopen(argv[1], "r")) == NULL) {
+        return 1;
+    }
+    while (fgets(line, sizeof line, f) != NULL) {
+        int n = 0;
+        char *tok;
+        for (tok = strtok(line, ",\n"); tok != NULL; tok = strtok(NULL, ",\n")) {
+            x[n++] = (FLOAT)strtod(tok, NULL);
+        }
+        model_predict(x, out);
+        for (i = 0; i < MODEL_N_OUTPUTS; i++) {
+            printf(i == 0 ? "%.17g" : ",%.17g", (double)out[i]);
+        }
+        printf("\n");
+    }
+    fclose(f);
+    return 0;
+}
+"#;
+
+    fn run_generated_source(
+        name: &str,
+        model: &MultiOutputForest,
+        options: &CCodegenOptions,
+        rows: &[Vec<f64>],
+    ) -> Vec<f64> {
+        let work_dir = std::env::temp_dir().join(format!(
+            "silva_codegen_c_{}_{name}_{:?}_{:?}_{}",
+            std::process::id(),
+            options.style,
+            options.float_type,
+            options.handle_missing
+        ));
+        fs::create_dir_all(&work_dir).unwrap();
+        let c_source = generate_c_source(model, options);
+        fs::write(work_dir.join(&c_source.header_name), &c_source.header).unwrap();
+        fs::write(work_dir.join("model.c"), &c_source.source).unwrap();
+        let harness = HARNESS.replace("FLOAT", options.float_type.name());
+        fs::write(work_dir.join("main.c"), harness).unwrap();
+        let x_path = work_dir.join("X.csv");
+        write_rows(&x_path, rows);
+
+        let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+        let bin_path = work_dir.join("main");
+        let status = Command::new(cc)
+            .args(["-std=c99", "-pedantic", "-Wall", "-Wextra", "-Werror", "-o"])
+            .arg(&bin_path)
+            .arg(work_dir.join("model.c"))
+            .arg(work_dir.join("main.c"))
+            .arg("-lm")
+            .status()
+            .expect("Failed to run the C compiler");
+        assert!(status.success(), "Generated C source failed to compile");
+
+        let output = Command::new(&bin_path).arg(&x_path).output().unwrap();
+        assert!(output.status.success());
+        fs::remove_dir_all(&work_dir).unwrap();
+
+        parse_outputs(&output.stdout)
+    }
+
+    fn test_generated_source(library: &str, model_type: &str, model: &MultiOutputForest) {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let x = read_features(&root.join(format!("test_data/{library}/{model_type}/X.csv")));
+        let x_missing = with_missing_values(&x);
+        let name = format!("{library}_{model_type}");
+
+        for style in [CodegenStyle::Branches, CodegenStyle::NodeTable] {
+            let options = CCodegenOptions {
+                style,
+                prefix: "model".to_string(),
+                ..Default::default()
+            };
+            let y = run_generated_source(&name, model, &options, &x);
+            assert!(
+                all_close(&y, &expected_outputs(model, &x), 1e-9),
+                "Generated {name} {style:?} source disagrees with MultiOutputForest::predict"
+            );
+            let y = run_generated_source(&name, model, &options, &x_missing);
+            assert!(
+                all_close(&y, &expected_outputs(model, &x_missing), 1e-9),
+                "Generated {name} {style:?} source mishandles missing values"
+            );
+
+            let options = CCodegenOptions {
+                float_type: CFloatType::Float,
+                handle_missing: false,
+                ..options
+            };
+            let y = run_generated_source(&name, model, &options, &x);
+            assert!(
+                all_close(&y, &expected_outputs(model, &x), 1e-3),
+                "Generated {name} {style:?} float source disagrees with MultiOutputForest::predict"
+            );
+        }
+    }
+
+    #[test]
+    fn test_xgboost_sources() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        for model_type in [
+            "regression",
+            "binary_classification",
+            "multiclass_classification",
+        ] {
+            let path = root.join(format!("test_data/xgboost/{model_type}/model.json"));
+            let model = read_xgboost_model(path).unwrap();
+            test_generated_source("xgboost", model_type, &model);
+        }
+    }
+
+    #[test]
+    fn test_lightgbm_sources() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        for model_type in [
+            "regression",
+            "binary_classification",
+            "multiclass_classification",
+        ] {
+            let path = root.join(format!("test_data/lightgbm/{model_type}/model.txt"));
+            let model = read_lightgbm_model(path).unwrap();
+            test_generated_source("lightgbm", model_type, &model);
+        }
+    }
+
+    #[test]
+    fn test_float_literal() {
+        assert_eq!(CFloatType::Double.literal(0.1), "0.1");
+        assert_eq!(CFloatType::Float.literal(0.1), "0.1f");
+        assert_eq!(CFloatType::Double.literal(1e35), "1e35");
+        assert_eq!(CFloatType::Double.literal(f64::NEG_INFINITY), "-INFINITY");
+    }
+}
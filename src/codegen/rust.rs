@@ -0,0 +1,352 @@
+use std::fmt::{self, Write};
+
+use crate::{Float, MultiOutputForest, Tree};
+
+use super::{CodegenStyle, flatten_tree};
+
+/// Options for [`generate_rust_module`].
+#[derive(Debug, Clone, Default)]
+pub struct RustCodegenOptions {
+    pub style: CodegenStyle,
+}
+
+/// Generate a standalone Rust module that evaluates `model` without any runtime loading.
+///
+/// The module exposes `pub fn predict(x: &[F]) -> [F; N]` returning one raw value per
+/// output, where `F` is `f32` or `f64` following the precision of `model`. It depends on
+/// nothing but `core`. Missing (NaN) values follow the default direction of each split, or go
+/// right when the model does not define one. It is meant to be written to `OUT_DIR` from a
+/// `build.rs` and pulled in with `include!`.
+pub fn generate_rust_module<F: Float>(
+    model: &MultiOutputForest<F>,
+    options: &RustCodegenOptions,
+) -> String {
+    let mut out = String::new();
+    write_rust_module(&mut out, model, options).expect("writing to a String cannot fail");
+    out
+}
+
+/// Same as [`generate_rust_module`], but writes the module into `out`.
+pub fn write_rust_module<F: Float>(
+    out: &mut impl Write,
+    model: &MultiOutputForest<F>,
+    options: &RustCodegenOptions,
+) -> fmt::Result {
+    let n_outputs = model.forests.len();
+
+    writeln!(out, "// @generated by silva. Do not edit by hand.")?;
+    writeln!(out)?;
+    writeln!(out, "/// Number of values returned by [`predict`].")?;
+    writeln!(out, "#[allow(dead_code)]")?;
+    writeln!(out, "pub const N_OUTPUTS: usize = {n_outputs};")?;
+    writeln!(out)?;
+
+    match options.style {
+        CodegenStyle::Branches => write_branches(out, model),
+        CodegenStyle::NodeTable => write_node_table(out, model),
+    }
+}
+
+fn write_branches<F: Float>(out: &mut impl Write, model: &MultiOutputForest<F>) -> fmt::Result {
+    let n_outputs = model.forests.len();
+    let ty = type_name::<F>();
+
+    writeln!(
+        out,
+        "/// Raw (untransformed) prediction, one value per output."
+    )?;
+    writeln!(out, "#[allow(clippy::all)]")?;
+    writeln!(out, "pub fn predict(x: &[{ty}]) -> [{ty}; {n_outputs}] {{")?;
+    writeln!(out, "    [")?;
+    for (i, forest) in model.forests.iter().enumerate() {
+        write!(out, "        {}", float_literal(forest.base_value))?;
+        if !forest.trees.is_empty() {
+            let calls = (0..forest.trees.len())
+                .map(|j| format!("tree_{i}_{j}(x)"))
+                .collect::<Vec<_>>()
+                .join(" + ");
+            // `Forest::predict` sums the trees first and adds the base value last
+            write!(out, " + ({calls})")?;
+        }
+        writeln!(out, ",")?;
+    }
+    writeln!(out, "    ]")?;
+    writeln!(out, "}}")?;
+
+    for (i, forest) in model.forests.iter().enumerate() {
+        for (j, tree) in forest.trees.iter().enumerate() {
+            writeln!(out)?;
+            writeln!(out, "#[allow(clippy::all)]")?;
+            writeln!(out, "#[inline(always)]")?;
+            writeln!(out, "fn tree_{i}_{j}(x: &[{ty}]) -> {ty} {{")?;
+            write_node(out, tree, tree.root, 1)?;
+            writeln!(out, "}}")?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_node<F: Float>(
+    out: &mut impl Write,
+    tree: &Tree<F>,
+    id: usize,
+    depth: usize,
+) -> fmt::Result {
+    let indent = "    ".repeat(depth);
+    let node = &tree.node_map[&id];
+    match (node.left, node.right) {
+        (Some(left), Some(right)) => {
+            let i = node.split_index;
+            let threshold = float_literal(node.split_condition.into_inner());
+            // NaN compares false, so only default-left splits need an explicit check
+            if node.default_left == Some(true) {
+                writeln!(out, "{indent}if x[{i}] < {threshold} || x[{i}].is_nan() {{")?;
+            } else {
+                writeln!(out, "{indent}if x[{i}] < {threshold} {{")?;
+            }
+            write_node(out, tree, left, depth + 1)?;
+            writeln!(out, "{indent}}} else {{")?;
+            write_node(out, tree, right, depth + 1)?;
+            writeln!(out, "{indent}}}")
+        }
+        _ => writeln!(out, "{indent}{}", float_literal(node.value.into_inner())),
+    }
+}
+
+fn write_node_table<F: Float>(out: &mut impl Write, model: &MultiOutputForest<F>) -> fmt::Result {
+    let n_outputs = model.forests.len();
+    let ty = type_name::<F>();
+
+    let mut table = Vec::new();
+    let roots: Vec<Vec<usize>> = model
+        .forests
+        .iter()
+        .map(|forest| {
+            forest
+                .trees
+                .iter()
+                .map(|tree| flatten_tree(tree, &mut table))
+                .collect()
+        })
+        .collect();
+
+    writeln!(out, "#[derive(Clone, Copy)]")?;
+    writeln!(out, "struct Node {{")?;
+    writeln!(out, "    split_index: u32,")?;
+    writeln!(
+        out,
+        "    /// Split threshold for internal nodes, leaf value for leaves."
+    )?;
+    writeln!(out, "    value: {ty},")?;
+    writeln!(out, "    left: u32,")?;
+    writeln!(out, "    right: u32,")?;
+    writeln!(out, "    default_left: bool,")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "const LEAF: u32 = u32::MAX;")?;
+    writeln!(out)?;
+    writeln!(out, "#[allow(clippy::all)]")?;
+    writeln!(out, "const NODES: &[Node] = &[")?;
+    for node in &table {
+        let (left, right) = match node.children {
+            Some((left, right)) => (left.to_string(), right.to_string()),
+            None => ("LEAF".to_string(), "LEAF".to_string()),
+        };
+        writeln!(
+            out,
+            "    Node {{ split_index: {}, value: {}, left: {left}, right: {right}, default_left: {} }},",
+            node.split_index,
+            float_literal(node.value),
+            node.default_left
+        )?;
+    }
+    writeln!(out, "];")?;
+    writeln!(out)?;
+    writeln!(out, "const ROOTS: [&[u32]; {n_outputs}] = [")?;
+    for output_roots in &roots {
+        let positions = output_roots
+            .iter()
+            .map(|pos| pos.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(out, "    &[{positions}],")?;
+    }
+    writeln!(out, "];")?;
+    writeln!(out)?;
+    writeln!(out, "#[allow(clippy::all)]")?;
+    writeln!(out, "const BASE_VALUES: [{ty}; {n_outputs}] = [")?;
+    for forest in &model.forests {
+        writeln!(out, "    {},", float_literal(forest.base_value))?;
+    }
+    writeln!(out, "];")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "/// Raw (untransformed) prediction, one value per output."
+    )?;
+    writeln!(out, "pub fn predict(x: &[{ty}]) -> [{ty}; {n_outputs}] {{")?;
+    writeln!(out, "    let mut out = [0.0; {n_outputs}];")?;
+    writeln!(out, "    for (i, roots) in ROOTS.iter().enumerate() {{")?;
+    writeln!(out, "        let mut sum = 0.0;")?;
+    writeln!(out, "        for &root in roots.iter() {{")?;
+    writeln!(out, "            let mut node = &NODES[root as usize];")?;
+    writeln!(out, "            while node.left != LEAF {{")?;
+    writeln!(out, "                let v = x[node.split_index as usize];")?;
+    writeln!(
+        out,
+        "                let next = if v < node.value || (node.default_left && v.is_nan()) {{"
+    )?;
+    writeln!(out, "                    node.left")?;
+    writeln!(out, "                }} else {{")?;
+    writeln!(out, "                    node.right")?;
+    writeln!(out, "                }};")?;
+    writeln!(out, "                node = &NODES[next as usize];")?;
+    writeln!(out, "            }}")?;
+    writeln!(out, "            sum += node.value;")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "        out[i] = BASE_VALUES[i] + sum;")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "    out")?;
+    writeln!(out, "}}")
+}
+
+/// `"f32"` or `"f64"`.
+fn type_name<F: Float>() -> &'static str {
+    std::any::type_name::<F>()
+}
+
+fn float_literal<F: Float>(v: F) -> String {
+    if v == F::infinity() {
+        format!("{}::INFINITY", type_name::<F>())
+    } else if v == F::neg_infinity() {
+        format!("{}::NEG_INFINITY", type_name::<F>())
+    } else {
+        // `Debug` prints the shortest representation that round-trips,
+        // and always includes a `.` or an exponent
+        format!("{v:?}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf, process::Command};
+
+    use super::*;
+    use crate::{
+        codegen::test_utils::{expected_outputs, parse_outputs, with_missing_values, write_rows},
+        parser::{
+            read_lightgbm_model, read_xgboost_model,
+            test_utils::{all_close, read_features},
+        },
+    };
+
+    const HARNESS: &str = r#"
+fn main() {
+    let path = std::env::args().nth(1).unwrap();
+    for line in std::fs::read_to_string(path).unwrap().lines() {
+        let x: Vec<FLOAT> = line.split(',').map(|s| s.parse().unwrap()).collect();
+        let y = model::predict(&x);
+        let y: Vec<String> = y.iter().map(|&v| format!("{:?}", f64::from(v))).collect();
+        println!("{}", y.join(","));
+    }
+}
+"#;
+
+    fn test_generated_module<F: Float>(
+        library: &str,
+        model_type: &str,
+        model: &MultiOutputForest<F>,
+        style: CodegenStyle,
+    ) {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let x = read_features(&root.join(format!("test_data/{library}/{model_type}/X.csv")));
+
+        let work_dir = std::env::temp_dir().join(format!(
+            "silva_codegen_rust_{}_{library}_{model_type}_{style:?}_{}",
+            std::process::id(),
+            type_name::<F>()
+        ));
+        fs::create_dir_all(&work_dir).unwrap();
+        let src_path = work_dir.join("main.rs");
+        let bin_path = work_dir.join("main");
+        let options = RustCodegenOptions { style };
+        let src = format!(
+            "mod model {{\n{}}}\n{}",
+            generate_rust_module(model, &options),
+            HARNESS.replace("FLOAT", type_name::<F>())
+        );
+        fs::write(&src_path, src).unwrap();
+
+        let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+        let status = Command::new(rustc)
+            .args(["--edition", "2021", "-o"])
+            .arg(&bin_path)
+            .arg(&src_path)
+            .status()
+            .expect("Failed to run rustc");
+        assert!(status.success(), "Generated module failed to compile");
+
+        for (kind, rows) in [
+            ("complete", x.clone()),
+            ("missing", with_missing_values(&x)),
+        ] {
+            let x_path = work_dir.join(format!("X_{kind}.csv"));
+            write_rows(&x_path, &rows);
+            let output = Command::new(&bin_path).arg(&x_path).output().unwrap();
+            assert!(output.status.success());
+            assert!(
+                all_close(
+                    &parse_outputs(&output.stdout),
+                    &expected_outputs(model, &rows),
+                    1e-9
+                ),
+                "Generated {library} {model_type} module disagrees with MultiOutputForest::predict on {kind} data"
+            );
+        }
+
+        fs::remove_dir_all(&work_dir).unwrap();
+    }
+    #[test]
+    fn test_xgboost_modules() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        for model_type in [
+            "regression",
+            "binary_classification",
+            "multiclass_classification",
+        ] {
+            let path = root.join(format!("test_data/xgboost/{model_type}/model.json"));
+            let model = read_xgboost_model(path).unwrap();
+            for style in [CodegenStyle::Branches, CodegenStyle::NodeTable] {
+                test_generated_module("xgboost", model_type, &model, style);
+                test_generated_module("xgboost", model_type, &model.cast::<f32>(), style);
+            }
+        }
+    }
+
+    #[test]
+    fn test_lightgbm_modules() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        for model_type in [
+            "regression",
+            "binary_classification",
+            "multiclass_classification",
+        ] {
+            let path = root.join(format!("test_data/lightgbm/{model_type}/model.txt"));
+            let model = read_lightgbm_model(path).unwrap();
+            for style in [CodegenStyle::Branches, CodegenStyle::NodeTable] {
+                test_generated_module("lightgbm", model_type, &model, style);
+            }
+        }
+    }
+
+    #[test]
+    fn test_float_literal() {
+        assert_eq!(float_literal(0.5), "0.5");
+        assert_eq!(float_literal(-2.0), "-2.0");
+        assert_eq!(float_literal(1e35), "1e35");
+        assert_eq!(float_literal(f64::INFINITY), "f64::INFINITY");
+        assert_eq!(float_literal(0.1_f32), "0.1");
+        assert_eq!(float_literal(f32::NEG_INFINITY), "f32::NEG_INFINITY");
+    }
+}
@@ -0,0 +1,167 @@
+//! Thresholded decisions that stop evaluating trees as soon as the outcome is known.
+
+use crate::{Float, Forest, float::summation_error};
+
+/// Outcome of [`Forest::decide`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decision {
+    /// Whether the raw prediction is above the threshold.
+    pub above: bool,
+    /// Number of trees evaluated before the outcome was certain.
+    pub trees_evaluated: usize,
+}
+
+/// Bounds of the sum of the trees from some position on.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct RemainingBounds {
+    min: f64,
+    max: f64,
+    /// Sum of the largest absolute leaf values, bounding the magnitude of every partial sum.
+    magnitude: f64,
+}
+
+impl<F: Float> Forest<F> {
+    fn remaining_bounds(&self) -> &[RemainingBounds] {
+        self.remaining_bounds.get_or_init(|| {
+            let mut bounds = vec![RemainingBounds::default(); self.trees.len() + 1];
+            for (i, tree) in self.trees.iter().enumerate().rev() {
+                let (min, max): (f64, f64) = {
+                    let (min, max) = tree.leaf_value_range();
+                    (min.into(), max.into())
+                };
+                let rest = bounds[i + 1];
+                bounds[i] = RemainingBounds {
+                    min: rest.min + min,
+                    max: rest.max + max,
+                    magnitude: rest.magnitude + min.abs().max(max.abs()),
+                };
+            }
+            bounds
+        })
+    }
+
+    /// Whether the raw prediction of `x` is above `threshold`, evaluating trees in order only
+    /// until the smallest and largest leaf values of the remaining trees can no longer change
+    /// the outcome.
+    ///
+    /// The outcome is always the one of `predict(x) > threshold`: bounds are checked with a
+    /// margin covering rounding differences between summation orders. Thresholds on
+    /// probabilities must be converted to raw scores first, e.g. with the logit for binary
+    /// logistic models.
+    pub fn decide(&self, x: &[F], threshold: F) -> Decision {
+        let bounds = self.remaining_bounds();
+        let threshold: f64 = threshold.into();
+        let base_value: f64 = self.base_value.into();
+        let slack =
+            summation_error::<F>(base_value.abs() + bounds[0].magnitude, self.trees.len() + 1);
+
+        // summed like `predict`, so the full evaluation matches it exactly
+        let mut sum = F::from_f64(0.0);
+        for (trees_evaluated, tree) in self.trees.iter().enumerate() {
+            let evaluated: f64 = sum.into();
+            let partial = base_value + evaluated;
+            let rest = bounds[trees_evaluated];
+            if partial + rest.min - slack > threshold {
+                return Decision {
+                    above: true,
+                    trees_evaluated,
+                };
+            }
+            if partial + rest.max + slack <= threshold {
+                return Decision {
+                    above: false,
+                    trees_evaluated,
+                };
+            }
+            sum = sum + tree.predict(x).into_inner();
+        }
+        Decision {
+            above: (self.base_value + sum).into() > threshold,
+            trees_evaluated: self.trees.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{
+        Tree, TreeNode,
+        parser::{read_lightgbm_model, read_xgboost_model, test_utils::read_features},
+    };
+
+    fn check_forest(forest: &Forest, rows: &[Vec<f64>]) {
+        let predictions: Vec<f64> = rows
+            .iter()
+            .map(|x| forest.predict(x).into_inner())
+            .collect();
+        let mut thresholds = predictions.clone();
+        thresholds.extend([-1e9, 1e9, 0.0]);
+        let mut early_exits = 0;
+        for &threshold in &thresholds {
+            for (x, prediction) in rows.iter().zip(&predictions) {
+                let decision = forest.decide(x, threshold);
+                assert_eq!(decision.above, *prediction > threshold);
+                assert!(decision.trees_evaluated <= forest.trees.len());
+                if decision.trees_evaluated < forest.trees.len() {
+                    early_exits += 1;
+                }
+            }
+        }
+        assert!(early_exits > 0);
+
+        let decision = forest.decide(&rows[0], -1e9);
+        assert_eq!(decision.trees_evaluated, 0);
+        assert!(decision.above);
+    }
+
+    #[test]
+    fn test_decide_xgboost() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/xgboost/binary_classification");
+        let model = read_xgboost_model(data_dir.join("model.json")).unwrap();
+        check_forest(&model.forests[0], &read_features(&data_dir.join("X.csv")));
+    }
+
+    #[test]
+    fn test_decide_lightgbm() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/lightgbm/binary_classification");
+        let model = read_lightgbm_model(data_dir.join("model.txt")).unwrap();
+        check_forest(&model.forests[0], &read_features(&data_dir.join("X.csv")));
+    }
+
+    fn stump(left: f32, right: f32) -> Tree<f32> {
+        Tree::from_nodes(vec![
+            TreeNode::split(0, 0, 0.5, 1, 2),
+            TreeNode::leaf(1, left),
+            TreeNode::leaf(2, right),
+        ])
+    }
+
+    #[test]
+    fn test_decide_cancelling_trees() {
+        // large leaf values of opposite signs, whose sums stay small but are rounded at the
+        // scale of the leaves
+        let trees = (0..200)
+            .map(|i| {
+                let offset = 0.0137 * i as f32;
+                if i % 2 == 0 {
+                    stump(1000.0 + offset, 1001.0 - offset)
+                } else {
+                    stump(-1001.0 + offset, -1000.0 - offset)
+                }
+            })
+            .collect();
+        let forest = Forest::new(0.1, trees);
+        for x in [[0.0], [1.0]] {
+            let prediction = forest.predict(&x).into_inner();
+            let step = prediction.abs().max(1.0) * f32::EPSILON;
+            for threshold in (-4..=4).map(|k| prediction + k as f32 * step) {
+                assert_eq!(forest.decide(&x, threshold).above, prediction > threshold);
+            }
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use thiserror::Error;
+
+use crate::{
+    binary::BinaryFormatError,
+    features::FeatureError,
+    parser::{LightGBMError, xgboost::XGBoostError},
+    quantize::QuantizeError,
+    sparse::SparseError,
+};
+
+/// Crate-wide error type, returned by the fallible prediction API and convertible from the
+/// errors of every module.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Expected {expected} features, got {actual}")]
+    FeatureCount { expected: usize, actual: usize },
+    #[error("Split at node {node} uses feature {feature}, but the row has only {actual} features")]
+    FeatureIndex {
+        feature: usize,
+        node: usize,
+        actual: usize,
+    },
+    #[error("Prediction is not finite")]
+    NonFiniteOutput,
+    #[error("Missing value for feature {feature} at node {node}, which has no default direction")]
+    MissingValue { feature: usize, node: usize },
+    #[error("Malformed tree: {message}")]
+    MalformedTree { message: String },
+    #[error("The model has no node {statistic} statistics")]
+    MissingStatistic { statistic: &'static str },
+    #[error("The background dataset is empty")]
+    EmptyBackground,
+    #[error(
+        "Invalid domain for feature {feature}: bounds must not be NaN and min must not exceed max"
+    )]
+    InvalidDomain { feature: usize },
+    #[error("The model does not record its source library, so its leaf numbering is unknown")]
+    UnknownLeafNumbering,
+    #[error("Monotonicity verification is inconclusive after {max_boxes} boxes")]
+    Inconclusive { max_boxes: usize },
+    #[error(transparent)]
+    XGBoost(#[from] XGBoostError),
+    #[error(transparent)]
+    LightGBM(#[from] LightGBMError),
+    #[error(transparent)]
+    BinaryFormat(#[from] BinaryFormatError),
+    #[error(transparent)]
+    Feature(#[from] FeatureError),
+    #[error(transparent)]
+    Sparse(#[from] SparseError),
+    #[error(transparent)]
+    Quantize(#[from] QuantizeError),
+}
+
+pub(crate) fn malformed_tree(message: impl Into<String>) -> Error {
+    Error::MalformedTree {
+        message: message.into(),
+    }
+}
@@ -0,0 +1,9 @@
+//! Explanations of individual predictions.
+
+mod interactions;
+mod interventional;
+mod saabas;
+mod tree_shap;
+
+pub use interventional::InterventionalExplainer;
+pub use saabas::{DecisionPath, DecisionStep};
@@ -0,0 +1,156 @@
+//! SHAP interaction values (Lundberg et al., "Consistent Individualized Feature Attribution for
+//! Tree Ensembles", section 4.2), computed like XGBoost's `pred_interactions`.
+
+use super::tree_shap::Condition;
+use crate::{Error, Float, Forest, MultiOutputForest};
+
+impl<F: Float> Forest<F> {
+    /// SHAP interaction values of `x` as a `(n + 1) × (n + 1)` matrix, `n` being `x.len()`.
+    ///
+    /// Entry `[i][j]` is half the interaction effect between features `i` and `j`, so the matrix
+    /// is symmetric, and the diagonal holds the main effects. Row `i` sums to the SHAP value of
+    /// feature `i`; the bias is entry `[n][n]` and the rest of the last row and column is zero,
+    /// matching the layout of XGBoost's `pred_interactions`.
+    pub fn shap_interaction_values(&self, x: &[F]) -> Result<Vec<Vec<F>>, Error> {
+        let n = x.len();
+        let mut phi = vec![0.0; n + 1];
+        self.add_shap_values(x, &mut phi, Condition::None)?;
+
+        let mut interactions = vec![vec![0.0; n + 1]; n + 1];
+        interactions[n][n] = phi[n];
+        for i in 0..n {
+            let mut present = vec![0.0; n + 1];
+            let mut absent = vec![0.0; n + 1];
+            self.add_shap_values(x, &mut present, Condition::Present(i))?;
+            self.add_shap_values(x, &mut absent, Condition::Absent(i))?;
+
+            interactions[i][i] = phi[i];
+            for j in (0..n).filter(|&j| j != i) {
+                let interaction = (present[j] - absent[j]) / 2.0;
+                interactions[i][j] = interaction;
+                interactions[i][i] -= interaction;
+            }
+        }
+        Ok(interactions
+            .into_iter()
+            .map(|row| row.into_iter().map(F::from_f64).collect())
+            .collect())
+    }
+}
+
+impl<F: Float> MultiOutputForest<F> {
+    /// SHAP interaction values of `x` for every output, see [`Forest::shap_interaction_values`].
+    pub fn shap_interaction_values(&self, x: &[F]) -> Result<Vec<Vec<Vec<F>>>, Error> {
+        self.forests
+            .iter()
+            .map(|forest| forest.shap_interaction_values(x))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+    use crate::{
+        explain::tree_shap::tests::{coalition_value, factorial},
+        parser::{
+            read_lightgbm_model, read_xgboost_model,
+            test_utils::{read_features, read_labels_flattened},
+        },
+    };
+
+    /// Shapley interaction index of features `i != j`, enumerating every coalition.
+    fn brute_force_interaction(forest: &Forest, x: &[f64], i: usize, j: usize) -> f64 {
+        let n = x.len();
+        let value = |coalition| coalition_value(forest, x, coalition);
+        let (i, j) = (1 << i, 1 << j);
+        let mut interaction = 0.0;
+        for coalition in (0..1u32 << n).filter(|c| c & (i | j) == 0) {
+            let size = coalition.count_ones() as usize;
+            let weight = factorial(size) * factorial(n - size - 2) / (2.0 * factorial(n - 1));
+            interaction += weight
+                * (value(coalition | i | j) - value(coalition | i) - value(coalition | j)
+                    + value(coalition));
+        }
+        interaction
+    }
+
+    fn check_model(model: &MultiOutputForest, data_dir: &Path) {
+        for (r, mut x) in read_features(&data_dir.join("X.csv"))
+            .into_iter()
+            .take(10)
+            .enumerate()
+        {
+            if r % 3 == 0 {
+                let i = r % x.len();
+                x[i] = f64::NAN;
+            }
+            let n = x.len();
+            let interactions = model.shap_interaction_values(&x).unwrap();
+            let shap = model.shap_values(&x).unwrap();
+            for ((forest, matrix), phi) in model.forests.iter().zip(&interactions).zip(&shap) {
+                assert_eq!(matrix.len(), n + 1);
+                for i in 0..=n {
+                    let total: f64 = matrix[i].iter().sum();
+                    assert!((total - phi[i]).abs() < 1e-9, "{total} != {}", phi[i]);
+                    for j in (0..n).filter(|&j| j != i) {
+                        assert!((matrix[i][j] - matrix[j][i]).abs() < 1e-9);
+                        if i < n {
+                            let expected = brute_force_interaction(forest, &x, i, j);
+                            assert!((matrix[i][j] - expected).abs() < 1e-9);
+                        } else {
+                            assert_eq!(matrix[i][j], 0.0);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_interaction_values_xgboost() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/xgboost/multiclass_classification");
+        let model = read_xgboost_model(data_dir.join("model.json")).unwrap();
+        check_model(&model, &data_dir);
+    }
+
+    /// Compare against `pred_interactions=True` output of XGBoost, which takes float32 inputs.
+    #[test]
+    fn test_interaction_values_match_xgboost() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        for dataset in [
+            "regression",
+            "binary_classification",
+            "multiclass_classification",
+        ] {
+            let data_dir = root.join("test_data/xgboost").join(dataset);
+            let model = read_xgboost_model(data_dir.join("model.json")).unwrap();
+            let expected = read_labels_flattened(&data_dir.join("interactions.csv"));
+            let rows = read_features(&data_dir.join("X.csv"));
+            let mut expected = expected.iter();
+            let mut max_error: f64 = 0.0;
+            for x in rows {
+                let x: Vec<f64> = x.iter().map(|&v| v as f32 as f64).collect();
+                for matrix in model.shap_interaction_values(&x).unwrap() {
+                    for (value, expected) in matrix.iter().flatten().zip(&mut expected) {
+                        let error = (value - expected).abs() / expected.abs().max(1.0);
+                        max_error = max_error.max(error);
+                    }
+                }
+            }
+            assert!(expected.next().is_none());
+            assert!(max_error < 1e-4, "{dataset}: relative error {max_error}");
+        }
+    }
+
+    #[test]
+    fn test_interaction_values_lightgbm() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/lightgbm/binary_classification");
+        let model = read_lightgbm_model(data_dir.join("model.txt")).unwrap();
+        check_model(&model, &data_dir);
+    }
+}
@@ -0,0 +1,312 @@
+//! Exact interventional TreeSHAP: attributions relative to a background dataset instead of the
+//! training covers, following the value function `v(S) = E_z[f(x_S, z_{N \ S})]` averaged over
+//! the background rows `z`.
+
+use rustc_hash::FxHashMap;
+
+use crate::{Error, Float, Forest, MultiOutputForest, Tree, TreeNode, error::malformed_tree};
+
+/// Direction (`true` for left) taken at every split of a tree, indexed by node position; leaves
+/// are `false`.
+fn routing<F: Float>(tree: &Tree<F>, row: &[F]) -> Result<Vec<bool>, Error> {
+    tree.node_map
+        .values()
+        .map(|node| {
+            if node.is_leaf() {
+                return Ok(false);
+            }
+            Ok(Some(node.try_next_node_id(node.try_feature(row)?)?) == node.left)
+        })
+        .collect()
+}
+
+fn child<'a, F: Float>(
+    tree: &'a Tree<F>,
+    node: &TreeNode<F>,
+    left: bool,
+) -> Result<&'a TreeNode<F>, Error> {
+    let id = if left { node.left } else { node.right };
+    let id = id.ok_or_else(|| malformed_tree(format!("node {} has a single child", node.id)))?;
+    tree.node(id)
+}
+
+/// Position of `node` in the node map, which indexes routings.
+fn position<F: Float>(tree: &Tree<F>, node: &TreeNode<F>) -> Result<usize, Error> {
+    tree.node_map
+        .get_index_of(&node.id)
+        .ok_or_else(|| malformed_tree(format!("node {} does not exist", node.id)))
+}
+
+/// Value of the leaf a routing ends in.
+fn routed_value<F: Float>(tree: &Tree<F>, routing: &[bool]) -> Result<f64, Error> {
+    let mut node = tree.node(tree.root)?;
+    for _ in 0..tree.node_map.len() {
+        if node.is_leaf() {
+            return Ok(node.value.into_inner().into());
+        }
+        node = child(tree, node, routing[position(tree, node)?])?;
+    }
+    Err(malformed_tree("cycle in tree"))
+}
+
+/// `(a - 1)! b! / (a + b)!`, the Shapley weight of a leaf reached with `a` features taken from
+/// the explained row and `b` from the background row, for a feature of the former.
+fn shapley_weight(a: usize, b: usize) -> f64 {
+    let binomial = (1..=b).fold(1.0, |acc, k| acc * (a + k) as f64 / k as f64);
+    1.0 / (a as f64 * binomial)
+}
+
+/// Background of one tree: the distinct routings of the background rows with the fraction of
+/// rows following each. Rows only matter through their routing, so trees with few splits explain
+/// a large background in a few traversals.
+#[derive(Debug, Clone)]
+struct TreeBackground {
+    routings: Vec<(Vec<bool>, f64)>,
+    expected_value: f64,
+}
+
+impl TreeBackground {
+    fn new<F: Float, R: AsRef<[F]>>(tree: &Tree<F>, background: &[R]) -> Result<Self, Error> {
+        let mut counts = FxHashMap::<Vec<bool>, usize>::default();
+        for row in background {
+            *counts.entry(routing(tree, row.as_ref())?).or_default() += 1;
+        }
+        let n_rows = background.len() as f64;
+        let routings: Vec<(Vec<bool>, f64)> = counts
+            .into_iter()
+            .map(|(routing, count)| (routing, count as f64 / n_rows))
+            .collect();
+
+        let mut expected_value = 0.0;
+        for (routing, fraction) in &routings {
+            expected_value += fraction * routed_value(tree, routing)?;
+        }
+        Ok(Self {
+            routings,
+            expected_value,
+        })
+    }
+}
+
+/// Traversal of a tree for one explained row and one background routing, following both rows
+/// and branching where they disagree on a feature that was not fixed yet.
+struct Walk<'a, F: Float> {
+    tree: &'a Tree<F>,
+    x: &'a [bool],
+    z: &'a [bool],
+    weight: f64,
+    phi: &'a mut [f64],
+    /// Features the rows disagreed on so far, with whether the explained row's value was used.
+    path: Vec<(usize, bool)>,
+}
+
+impl<'a, F: Float> Walk<'a, F> {
+    fn new(
+        tree: &'a Tree<F>,
+        x: &'a [bool],
+        z: &'a [bool],
+        weight: f64,
+        phi: &'a mut [f64],
+    ) -> Self {
+        Self {
+            tree,
+            x,
+            z,
+            weight,
+            phi,
+            path: Vec::new(),
+        }
+    }
+
+    fn visit(&mut self, node: &'a TreeNode<F>, depth: usize) -> Result<(), Error> {
+        if depth > self.tree.node_map.len() {
+            return Err(malformed_tree("cycle in tree"));
+        }
+        if node.is_leaf() {
+            let value: f64 = node.value.into_inner().into();
+            let value = value * self.weight;
+            let from_x = self.path.iter().filter(|(_, from_x)| *from_x).count();
+            let from_z = self.path.len() - from_x;
+            for &(feature, is_x) in &self.path {
+                if is_x {
+                    self.phi[feature] += value * shapley_weight(from_x, from_z);
+                } else {
+                    self.phi[feature] -= value * shapley_weight(from_z, from_x);
+                }
+            }
+            return Ok(());
+        }
+
+        let position = position(self.tree, node)?;
+        let (x_left, z_left) = (self.x[position], self.z[position]);
+        if x_left == z_left {
+            return self.visit(child(self.tree, node, x_left)?, depth + 1);
+        }
+        // a feature already fixed to one row keeps following that row
+        if let Some(&(_, is_x)) = self.path.iter().find(|(f, _)| *f == node.split_index) {
+            let left = if is_x { x_left } else { z_left };
+            return self.visit(child(self.tree, node, left)?, depth + 1);
+        }
+        for (is_x, left) in [(true, x_left), (false, z_left)] {
+            self.path.push((node.split_index, is_x));
+            self.visit(child(self.tree, node, left)?, depth + 1)?;
+            self.path.pop();
+        }
+        Ok(())
+    }
+}
+
+/// Interventional TreeSHAP explainer of a [`Forest`] against a background dataset.
+///
+/// The background is routed through every tree once when the explainer is built, and each tree
+/// keeps the distinct routings with one direction per node. A tree with `s` splits and `n` nodes
+/// has at most `min(rows, 2^s)` distinct routings, so the explainer holds
+/// `O(min(rows, 2^s) * n)` flags per tree, and explaining a row costs one traversal per tree and
+/// distinct routing. Both only stop growing with the background once every routing is seen,
+/// which happens for shallow trees but not in general.
+#[derive(Debug, Clone)]
+pub struct InterventionalExplainer<'a, F: Float = f64> {
+    forest: &'a Forest<F>,
+    trees: Vec<TreeBackground>,
+}
+
+impl<'a, F: Float> InterventionalExplainer<'a, F> {
+    /// Route the `background` rows through every tree of `forest`.
+    pub fn new<R: AsRef<[F]>>(forest: &'a Forest<F>, background: &[R]) -> Result<Self, Error> {
+        if background.is_empty() {
+            return Err(Error::EmptyBackground);
+        }
+        let trees = forest
+            .trees
+            .iter()
+            .map(|tree| TreeBackground::new(tree, background))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { forest, trees })
+    }
+
+    /// Mean raw prediction over the background, the bias of the attributions.
+    pub fn expected_value(&self) -> F {
+        let trees: f64 = self.trees.iter().map(|tree| tree.expected_value).sum();
+        F::from_f64(self.forest.base_value.into() + trees)
+    }
+
+    /// Interventional SHAP values of `x`: one contribution per feature followed by the bias
+    /// ([`Self::expected_value`]), summing to the raw prediction of `x`.
+    pub fn shap_values(&self, x: &[F]) -> Result<Vec<F>, Error> {
+        let mut phi = vec![0.0; x.len() + 1];
+        for (tree, background) in self.forest.trees.iter().zip(&self.trees) {
+            let x_routing = routing(tree, x)?;
+            let root = tree.node(tree.root)?;
+            for (z_routing, fraction) in &background.routings {
+                Walk::new(tree, &x_routing, z_routing, *fraction, &mut phi).visit(root, 0)?;
+            }
+        }
+        phi[x.len()] = self.expected_value().into();
+        Ok(phi.into_iter().map(F::from_f64).collect())
+    }
+}
+
+impl<F: Float> MultiOutputForest<F> {
+    /// One [`InterventionalExplainer`] per output against the same `background`.
+    pub fn interventional_explainers<R: AsRef<[F]>>(
+        &self,
+        background: &[R],
+    ) -> Result<Vec<InterventionalExplainer<'_, F>>, Error> {
+        self.forests
+            .iter()
+            .map(|forest| InterventionalExplainer::new(forest, background))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{
+        explain::tree_shap::tests::factorial,
+        parser::{read_lightgbm_model, read_xgboost_model, test_utils::read_features},
+    };
+
+    /// Interventional Shapley values by enumerating every coalition and background row.
+    fn brute_force_shap(forest: &Forest, x: &[f64], background: &[Vec<f64>]) -> Vec<f64> {
+        let n = x.len();
+        let value = |coalition: u32| -> f64 {
+            let total: f64 = background
+                .iter()
+                .map(|z| {
+                    let row: Vec<f64> = (0..n)
+                        .map(|i| {
+                            if coalition & (1 << i) != 0 {
+                                x[i]
+                            } else {
+                                z[i]
+                            }
+                        })
+                        .collect();
+                    forest.predict(&row).into_inner()
+                })
+                .sum();
+            total / background.len() as f64
+        };
+        let mut phi = vec![0.0; n + 1];
+        for (i, phi_i) in phi.iter_mut().enumerate().take(n) {
+            for coalition in (0..1u32 << n).filter(|c| c & (1 << i) == 0) {
+                let size = coalition.count_ones() as usize;
+                let weight = factorial(size) * factorial(n - size - 1) / factorial(n);
+                *phi_i += weight * (value(coalition | (1 << i)) - value(coalition));
+            }
+        }
+        phi[n] = value(0);
+        phi
+    }
+
+    fn check_model(model: &MultiOutputForest, rows: Vec<Vec<f64>>) {
+        let (background, rows) = rows.split_at(30);
+        let explainers = model.interventional_explainers(background).unwrap();
+        for (r, x) in rows.iter().take(5).enumerate() {
+            let mut x = x.clone();
+            if r % 2 == 0 {
+                x[r] = f64::NAN;
+            }
+            let predictions = model.predict(&x);
+            for ((explainer, forest), prediction) in
+                explainers.iter().zip(&model.forests).zip(predictions)
+            {
+                let phi = explainer.shap_values(&x).unwrap();
+                let total: f64 = phi.iter().sum();
+                assert!((total - prediction.into_inner()).abs() < 1e-9);
+                for (a, b) in phi.iter().zip(brute_force_shap(forest, &x, background)) {
+                    assert!((a - b).abs() < 1e-9, "{a} != {b}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_interventional_xgboost() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/xgboost/regression");
+        let model = read_xgboost_model(data_dir.join("model.json")).unwrap();
+        check_model(&model, read_features(&data_dir.join("X.csv")));
+    }
+
+    #[test]
+    fn test_interventional_lightgbm() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/lightgbm/multiclass_classification");
+        let model = read_lightgbm_model(data_dir.join("model.txt")).unwrap();
+        check_model(&model, read_features(&data_dir.join("X.csv")));
+    }
+
+    #[test]
+    fn test_empty_background() {
+        let forest = Forest::new(0.0, Vec::new());
+        let background: &[Vec<f64>] = &[];
+        assert!(matches!(
+            InterventionalExplainer::new(&forest, background),
+            Err(Error::EmptyBackground)
+        ));
+    }
+}
@@ -0,0 +1,211 @@
+//! Decision paths and Saabas contributions, which attribute the change in expected output at
+//! each split along the path of a row to the split feature.
+
+use crate::{Error, Float, Forest, MultiOutputForest, Tree, TreeNode, error::malformed_tree};
+
+/// One split along a [`DecisionPath`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecisionStep<F: Float = f64> {
+    /// Id of the split node.
+    pub node: usize,
+    pub feature: usize,
+    pub threshold: F,
+    /// Whether the row went to the left child, i.e. had a feature value below the threshold.
+    pub left: bool,
+    /// Whether the feature was missing, so the row took the default direction.
+    pub missing: bool,
+    /// Expected output of the node before the split, if the model records it.
+    pub value: Option<F>,
+}
+
+/// Splits a row goes through from the root of a tree to a leaf.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecisionPath<F: Float = f64> {
+    pub steps: Vec<DecisionStep<F>>,
+    /// Id of the leaf reached.
+    pub leaf: usize,
+    /// Output of the leaf, the prediction of the tree.
+    pub value: F,
+}
+
+impl<F: Float> DecisionPath<F> {
+    /// Expected output before every split followed by the leaf value, so consecutive differences
+    /// are the changes caused by each split.
+    fn running_values(&self) -> Result<Vec<f64>, Error> {
+        let mut values = self
+            .steps
+            .iter()
+            .map(|step| {
+                step.value.map(Into::into).ok_or(Error::MissingStatistic {
+                    statistic: "internal value",
+                })
+            })
+            .collect::<Result<Vec<f64>, _>>()?;
+        values.push(self.value.into());
+        Ok(values)
+    }
+}
+
+impl<F: Float> Tree<F> {
+    /// Path of `x` through the tree, with the expected output of every split node on the way.
+    pub fn decision_path(&self, x: &[F]) -> Result<DecisionPath<F>, Error> {
+        let mut steps = Vec::new();
+        let mut node: &TreeNode<F> = self.node(self.root)?;
+        while !node.is_leaf() {
+            if steps.len() >= self.node_map.len() {
+                return Err(malformed_tree("cycle in tree"));
+            }
+            let feature = node.try_feature(x)?;
+            let next = node.try_next_node_id(feature)?;
+            steps.push(DecisionStep {
+                node: node.id,
+                feature: node.split_index,
+                threshold: node.split_condition.into_inner(),
+                left: Some(next) == node.left,
+                missing: feature.is_nan(),
+                value: node.internal_value.map(|v| v.into_inner()),
+            });
+            node = self.node(next)?;
+        }
+        Ok(DecisionPath {
+            steps,
+            leaf: node.id,
+            value: node.value.into_inner(),
+        })
+    }
+
+    /// Add the Saabas contributions of this tree for `x` to `phi`, which has one entry per
+    /// feature followed by the bias.
+    fn add_saabas_contributions(&self, x: &[F], phi: &mut [f64]) -> Result<(), Error> {
+        let path = self.decision_path(x)?;
+        let values = path.running_values()?;
+        phi[x.len()] += values[0];
+        for (step, change) in path.steps.iter().zip(values.windows(2)) {
+            phi[step.feature] += change[1] - change[0];
+        }
+        Ok(())
+    }
+
+    /// Saabas contributions of `x`: for each feature, the change in expected output at the
+    /// splits on it along the decision path, followed by the bias (the expected output of the
+    /// root). They sum to [`Tree::predict`].
+    ///
+    /// Cheaper than [`Tree::shap_values`] but not consistent: splits near the root get less
+    /// credit than they deserve. Requires the internal node values the XGBoost and LightGBM
+    /// parsers keep.
+    pub fn saabas_contributions(&self, x: &[F]) -> Result<Vec<F>, Error> {
+        let mut phi = vec![0.0; x.len() + 1];
+        self.add_saabas_contributions(x, &mut phi)?;
+        Ok(phi.into_iter().map(F::from_f64).collect())
+    }
+}
+
+impl<F: Float> Forest<F> {
+    /// Saabas contributions of `x` summed over the trees, see [`Tree::saabas_contributions`];
+    /// the bias includes the base value.
+    pub fn saabas_contributions(&self, x: &[F]) -> Result<Vec<F>, Error> {
+        let mut phi = vec![0.0; x.len() + 1];
+        for tree in &self.trees {
+            tree.add_saabas_contributions(x, &mut phi)?;
+        }
+        phi[x.len()] += self.base_value.into();
+        Ok(phi.into_iter().map(F::from_f64).collect())
+    }
+
+    /// Decision path of `x` through every tree.
+    pub fn decision_paths(&self, x: &[F]) -> Result<Vec<DecisionPath<F>>, Error> {
+        self.trees
+            .iter()
+            .map(|tree| tree.decision_path(x))
+            .collect()
+    }
+}
+
+impl<F: Float> MultiOutputForest<F> {
+    /// Saabas contributions of `x` for every output, see [`Forest::saabas_contributions`].
+    pub fn saabas_contributions(&self, x: &[F]) -> Result<Vec<Vec<F>>, Error> {
+        self.forests
+            .iter()
+            .map(|forest| forest.saabas_contributions(x))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+    use crate::parser::{read_lightgbm_model, read_xgboost_model, test_utils::read_features};
+
+    fn check_model(model: &MultiOutputForest, data_dir: &Path) {
+        for (r, mut x) in read_features(&data_dir.join("X.csv"))
+            .into_iter()
+            .enumerate()
+        {
+            if r % 3 == 0 {
+                let i = r % x.len();
+                x[i] = f64::NAN;
+            }
+            let contributions = model.saabas_contributions(&x).unwrap();
+            for ((forest, phi), prediction) in model
+                .forests
+                .iter()
+                .zip(&contributions)
+                .zip(model.predict(&x))
+            {
+                let total: f64 = phi.iter().sum();
+                assert!((total - prediction.into_inner()).abs() < 1e-9);
+
+                for (tree, path) in forest.trees.iter().zip(forest.decision_paths(&x).unwrap()) {
+                    assert_eq!(path.value, tree.predict(&x).into_inner());
+                    assert_eq!(path.steps[0].node, tree.root);
+                    for step in &path.steps {
+                        assert_eq!(step.missing, x[step.feature].is_nan());
+                        if !step.missing {
+                            assert_eq!(step.left, x[step.feature] < step.threshold);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_saabas_xgboost() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/xgboost/multiclass_classification");
+        let model = read_xgboost_model(data_dir.join("model.json")).unwrap();
+        check_model(&model, &data_dir);
+
+        // split node values are the cover-weighted means of their leaves
+        for tree in &model.forests[0].trees {
+            let root = tree.node(tree.root).unwrap();
+            let expected = tree.expected_value_at(root, 0).unwrap();
+            assert!((root.internal_value.unwrap().into_inner() - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_saabas_lightgbm() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/lightgbm/multiclass_classification");
+        let model = read_lightgbm_model(data_dir.join("model.txt")).unwrap();
+        check_model(&model, &data_dir);
+    }
+
+    #[test]
+    fn test_missing_internal_values() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let model_path = root.join("test_data/native/multiclass_classification/model.json");
+        let model = MultiOutputForest::<f64>::from_file(model_path).unwrap();
+        let x = [0.0; 5];
+        assert!(model.forests[0].trees[0].decision_path(&x).is_ok());
+        assert!(matches!(
+            model.saabas_contributions(&x),
+            Err(Error::MissingStatistic {
+                statistic: "internal value"
+            })
+        ));
+    }
+}
@@ -0,0 +1,458 @@
+//! Exact path-dependent TreeSHAP (Lundberg et al., "Consistent Individualized Feature Attribution
+//! for Tree Ensembles", algorithm 2).
+
+use crate::{Error, Float, Forest, MultiOutputForest, Tree, TreeNode, error::malformed_tree};
+
+/// Sentinel feature of the root path element, which never receives attributions.
+const NO_FEATURE: usize = usize::MAX;
+
+#[derive(Debug, Clone, Copy)]
+struct PathElement {
+    feature_index: usize,
+    /// Fraction of the cover flowing down this path when the feature is not in the coalition.
+    zero_fraction: f64,
+    /// Whether `x` follows this path when the feature is in the coalition.
+    one_fraction: f64,
+    /// Proportion of coalitions of each size that go down this path.
+    pweight: f64,
+}
+
+impl PathElement {
+    fn new(feature_index: usize, zero_fraction: f64, one_fraction: f64) -> Self {
+        Self {
+            feature_index,
+            zero_fraction,
+            one_fraction,
+            pweight: 0.0,
+        }
+    }
+}
+
+fn extend_path(path: &mut Vec<PathElement>, element: PathElement) {
+    let depth = path.len();
+    let PathElement {
+        zero_fraction,
+        one_fraction,
+        ..
+    } = element;
+    path.push(PathElement {
+        pweight: if depth == 0 { 1.0 } else { 0.0 },
+        ..element
+    });
+    for i in (0..depth).rev() {
+        path[i + 1].pweight += one_fraction * path[i].pweight * (i + 1) as f64 / (depth + 1) as f64;
+        path[i].pweight = zero_fraction * path[i].pweight * (depth - i) as f64 / (depth + 1) as f64;
+    }
+}
+
+/// Undo [`extend_path`] for the element at `path_index`.
+fn unwind_path(path: &mut Vec<PathElement>, path_index: usize) {
+    let depth = path.len() - 1;
+    let PathElement {
+        zero_fraction,
+        one_fraction,
+        ..
+    } = path[path_index];
+    let mut next_one_portion = path[depth].pweight;
+    for i in (0..depth).rev() {
+        if one_fraction != 0.0 {
+            let tmp = path[i].pweight;
+            path[i].pweight =
+                next_one_portion * (depth + 1) as f64 / ((i + 1) as f64 * one_fraction);
+            next_one_portion =
+                tmp - path[i].pweight * zero_fraction * (depth - i) as f64 / (depth + 1) as f64;
+        } else {
+            path[i].pweight =
+                path[i].pweight * (depth + 1) as f64 / (zero_fraction * (depth - i) as f64);
+        }
+    }
+    for i in path_index..depth {
+        path[i].feature_index = path[i + 1].feature_index;
+        path[i].zero_fraction = path[i + 1].zero_fraction;
+        path[i].one_fraction = path[i + 1].one_fraction;
+    }
+    path.pop();
+}
+
+/// Total permutation weight of the path with the element at `path_index` unwound.
+fn unwound_path_sum(path: &[PathElement], path_index: usize) -> f64 {
+    let depth = path.len() - 1;
+    let PathElement {
+        zero_fraction,
+        one_fraction,
+        ..
+    } = path[path_index];
+    let mut next_one_portion = path[depth].pweight;
+    let mut total = 0.0;
+    for i in (0..depth).rev() {
+        let scale = (depth - i) as f64 / (depth + 1) as f64;
+        if one_fraction != 0.0 {
+            let tmp = next_one_portion * (depth + 1) as f64 / ((i + 1) as f64 * one_fraction);
+            total += tmp;
+            next_one_portion = path[i].pweight - tmp * zero_fraction * scale;
+        } else if zero_fraction != 0.0 {
+            total += path[i].pweight / zero_fraction / scale;
+        }
+    }
+    total
+}
+
+/// Feature the contributions are conditioned on, used for interaction values.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Condition {
+    None,
+    /// The feature is in every coalition.
+    Present(usize),
+    /// The feature is in no coalition.
+    Absent(usize),
+}
+
+impl Condition {
+    fn feature(self) -> Option<usize> {
+        match self {
+            Condition::None => None,
+            Condition::Present(feature) | Condition::Absent(feature) => Some(feature),
+        }
+    }
+}
+
+struct ShapState<'a, F> {
+    x: &'a [F],
+    phi: &'a mut [f64],
+    condition: Condition,
+}
+
+pub(crate) fn cover<F: Float>(node: &TreeNode<F>) -> Result<f64, Error> {
+    node.cover
+        .map(|c| c.into_inner().into())
+        .ok_or(Error::MissingStatistic { statistic: "cover" })
+}
+
+impl<F: Float> Tree<F> {
+    pub(crate) fn node(&self, id: usize) -> Result<&TreeNode<F>, Error> {
+        self.node_map
+            .get(&id)
+            .ok_or_else(|| malformed_tree(format!("node {id} does not exist")))
+    }
+
+    /// Children of a split node as `(hot, cold)`: the one `x` follows and the other one.
+    pub(crate) fn hot_cold(
+        &self,
+        node: &TreeNode<F>,
+        x: &[F],
+    ) -> Result<(&TreeNode<F>, &TreeNode<F>), Error> {
+        let hot = node.try_next_node_id(node.try_feature(x)?)?;
+        let (left, right) = node
+            .left
+            .zip(node.right)
+            .ok_or_else(|| malformed_tree(format!("node {} has a single child", node.id)))?;
+        let cold = if hot == left { right } else { left };
+        Ok((self.node(hot)?, self.node(cold)?))
+    }
+
+    /// Expected output of the subtree at `node`, found `depth` splits below the root, weighting
+    /// branches by cover.
+    pub(crate) fn expected_value_at(&self, node: &TreeNode<F>, depth: usize) -> Result<f64, Error> {
+        if depth >= self.node_map.len() {
+            return Err(malformed_tree("cycle in tree"));
+        }
+        if node.is_leaf() {
+            return Ok(node.value.into_inner().into());
+        }
+        let (left, right) = node
+            .left
+            .zip(node.right)
+            .ok_or_else(|| malformed_tree(format!("node {} has a single child", node.id)))?;
+        let (left, right) = (self.node(left)?, self.node(right)?);
+        Ok((cover(left)? * self.expected_value_at(left, depth + 1)?
+            + cover(right)? * self.expected_value_at(right, depth + 1)?)
+            / cover(node)?)
+    }
+
+    /// Expected output of the tree over the training data, weighting branches by cover.
+    pub fn expected_value(&self) -> Result<F, Error> {
+        Ok(F::from_f64(
+            self.expected_value_at(self.node(self.root)?, 0)?,
+        ))
+    }
+
+    fn shap_recursive(
+        &self,
+        state: &mut ShapState<F>,
+        node: &TreeNode<F>,
+        mut path: Vec<PathElement>,
+        element: PathElement,
+        condition_fraction: f64,
+        depth: usize,
+    ) -> Result<(), Error> {
+        if depth >= self.node_map.len() {
+            return Err(malformed_tree("cycle in tree"));
+        }
+        if condition_fraction == 0.0 {
+            return Ok(());
+        }
+        // the conditioned feature is left out of the coalitions
+        if state.condition.feature() != Some(element.feature_index) {
+            extend_path(&mut path, element);
+        }
+
+        if node.is_leaf() {
+            let value: f64 = node.value.into_inner().into();
+            for i in 1..path.len() {
+                let weight = unwound_path_sum(&path, i);
+                let element = path[i];
+                state.phi[element.feature_index] += weight
+                    * (element.one_fraction - element.zero_fraction)
+                    * value
+                    * condition_fraction;
+            }
+            return Ok(());
+        }
+
+        let (hot, cold) = self.hot_cold(node, state.x)?;
+        let node_cover = cover(node)?;
+        let hot_zero_fraction = cover(hot)? / node_cover;
+        let cold_zero_fraction = cover(cold)? / node_cover;
+
+        // a feature split on again along the path is undone first, keeping its fractions
+        let mut incoming_zero_fraction = 1.0;
+        let mut incoming_one_fraction = 1.0;
+        if let Some(k) = (1..path.len()).find(|&k| path[k].feature_index == node.split_index) {
+            incoming_zero_fraction = path[k].zero_fraction;
+            incoming_one_fraction = path[k].one_fraction;
+            unwind_path(&mut path, k);
+        }
+
+        let (hot_condition_fraction, cold_condition_fraction) = match state.condition {
+            Condition::Present(feature) if feature == node.split_index => (condition_fraction, 0.0),
+            Condition::Absent(feature) if feature == node.split_index => (
+                condition_fraction * hot_zero_fraction,
+                condition_fraction * cold_zero_fraction,
+            ),
+            _ => (condition_fraction, condition_fraction),
+        };
+
+        self.shap_recursive(
+            state,
+            hot,
+            path.clone(),
+            PathElement::new(
+                node.split_index,
+                hot_zero_fraction * incoming_zero_fraction,
+                incoming_one_fraction,
+            ),
+            hot_condition_fraction,
+            depth + 1,
+        )?;
+        self.shap_recursive(
+            state,
+            cold,
+            path,
+            PathElement::new(
+                node.split_index,
+                cold_zero_fraction * incoming_zero_fraction,
+                0.0,
+            ),
+            cold_condition_fraction,
+            depth + 1,
+        )
+    }
+
+    /// Add the contributions of this tree for `x` to `phi`, which has one entry per feature
+    /// followed by the bias. The bias is only added without condition.
+    pub(crate) fn add_shap_values(
+        &self,
+        x: &[F],
+        phi: &mut [f64],
+        condition: Condition,
+    ) -> Result<(), Error> {
+        let root = self.node(self.root)?;
+        let mut state = ShapState { x, phi, condition };
+        let element = PathElement::new(NO_FEATURE, 1.0, 1.0);
+        self.shap_recursive(&mut state, root, Vec::new(), element, 1.0, 0)?;
+        if let Condition::None = condition {
+            phi[x.len()] += self.expected_value_at(root, 0)?;
+        }
+        Ok(())
+    }
+
+    /// SHAP values of `x`: one contribution per feature followed by the bias (the expected
+    /// value of the tree), summing to [`Tree::predict`].
+    ///
+    /// Requires node covers, which the XGBoost and LightGBM parsers keep.
+    pub fn shap_values(&self, x: &[F]) -> Result<Vec<F>, Error> {
+        let mut phi = vec![0.0; x.len() + 1];
+        self.add_shap_values(x, &mut phi, Condition::None)?;
+        Ok(phi.into_iter().map(F::from_f64).collect())
+    }
+}
+
+impl<F: Float> Forest<F> {
+    pub(crate) fn add_shap_values(
+        &self,
+        x: &[F],
+        phi: &mut [f64],
+        condition: Condition,
+    ) -> Result<(), Error> {
+        for tree in &self.trees {
+            tree.add_shap_values(x, phi, condition)?;
+        }
+        if let Condition::None = condition {
+            phi[x.len()] += self.base_value.into();
+        }
+        Ok(())
+    }
+
+    /// SHAP values of `x`, see [`Tree::shap_values`]; the bias includes the base value.
+    pub fn shap_values(&self, x: &[F]) -> Result<Vec<F>, Error> {
+        let mut phi = vec![0.0; x.len() + 1];
+        self.add_shap_values(x, &mut phi, Condition::None)?;
+        Ok(phi.into_iter().map(F::from_f64).collect())
+    }
+}
+
+impl<F: Float> MultiOutputForest<F> {
+    /// SHAP values of `x` for every output, see [`Forest::shap_values`].
+    pub fn shap_values(&self, x: &[F]) -> Result<Vec<Vec<F>>, Error> {
+        self.forests
+            .iter()
+            .map(|forest| forest.shap_values(x))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::parser::{read_lightgbm_model, read_xgboost_model, test_utils::read_features};
+
+    /// Expected output of the subtree at `node` given only the features in `coalition`, the
+    /// value function of path-dependent TreeSHAP.
+    pub(crate) fn conditional_expectation(
+        tree: &Tree,
+        node: &TreeNode,
+        x: &[f64],
+        coalition: u32,
+    ) -> f64 {
+        if node.is_leaf() {
+            return node.value.into_inner();
+        }
+        let (hot, cold) = tree.hot_cold(node, x).unwrap();
+        if coalition & (1 << node.split_index) != 0 {
+            conditional_expectation(tree, hot, x, coalition)
+        } else {
+            let node_cover = cover(node).unwrap();
+            (cover(hot).unwrap() * conditional_expectation(tree, hot, x, coalition)
+                + cover(cold).unwrap() * conditional_expectation(tree, cold, x, coalition))
+                / node_cover
+        }
+    }
+
+    /// Expected output of the forest (without base value) given the features in `coalition`.
+    pub(crate) fn coalition_value(forest: &Forest, x: &[f64], coalition: u32) -> f64 {
+        forest
+            .trees
+            .iter()
+            .map(|tree| {
+                let root = tree.node(tree.root).unwrap();
+                conditional_expectation(tree, root, x, coalition)
+            })
+            .sum()
+    }
+
+    pub(crate) fn factorial(k: usize) -> f64 {
+        (1..=k).map(|v| v as f64).product()
+    }
+
+    /// Shapley values of the forest by enumerating every coalition.
+    pub(crate) fn brute_force_shap(forest: &Forest, x: &[f64]) -> Vec<f64> {
+        let n = x.len();
+        let value = |coalition| coalition_value(forest, x, coalition);
+        let mut phi = vec![0.0; n + 1];
+        for (i, phi_i) in phi.iter_mut().enumerate().take(n) {
+            for coalition in 0..1u32 << n {
+                if coalition & (1 << i) != 0 {
+                    continue;
+                }
+                let size = coalition.count_ones() as usize;
+                let weight = factorial(size) * factorial(n - size - 1) / factorial(n);
+                *phi_i += weight * (value(coalition | (1 << i)) - value(coalition));
+            }
+        }
+        phi[n] = forest.base_value + value(0);
+        phi
+    }
+
+    fn check_model(model: &MultiOutputForest, data_dir: &std::path::Path) {
+        for (r, mut x) in read_features(&data_dir.join("X.csv"))
+            .into_iter()
+            .enumerate()
+        {
+            if r % 3 == 0 {
+                let i = r % x.len();
+                x[i] = f64::NAN;
+            }
+            let shap = model.shap_values(&x).unwrap();
+            for ((forest, phi), prediction) in
+                model.forests.iter().zip(&shap).zip(model.predict(&x))
+            {
+                let total: f64 = phi.iter().sum();
+                assert!(
+                    (total - prediction.into_inner()).abs() < 1e-9,
+                    "{total} {prediction}"
+                );
+                for (a, b) in phi.iter().zip(brute_force_shap(forest, &x)) {
+                    assert!((a - b).abs() < 1e-9, "{a} != {b}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_shap_values_xgboost() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/xgboost/multiclass_classification");
+        let model = read_xgboost_model(data_dir.join("model.json")).unwrap();
+        check_model(&model, &data_dir);
+    }
+
+    #[test]
+    fn test_shap_values_lightgbm() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/lightgbm/binary_classification");
+        let model = read_lightgbm_model(data_dir.join("model.txt")).unwrap();
+        check_model(&model, &data_dir);
+    }
+
+    #[test]
+    fn test_missing_covers() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let model_path = root.join("test_data/native/multiclass_classification/model.json");
+        let model = MultiOutputForest::<f64>::from_file(model_path).unwrap();
+        assert!(matches!(
+            model.shap_values(&[0.0; 5]),
+            Err(Error::MissingStatistic { statistic: "cover" })
+        ));
+    }
+
+    #[test]
+    fn test_cycle() {
+        // the split lists itself as its left child and follows it for x[0] < 1
+        let tree = Tree::from_nodes(vec![
+            TreeNode::split(0, 0, 1.0, 0, 1).with_cover(2.0),
+            TreeNode::leaf(1, 1.0).with_cover(1.0),
+        ]);
+        for x in [[0.0], [2.0]] {
+            assert!(matches!(
+                tree.shap_values(&x),
+                Err(Error::MalformedTree { .. })
+            ));
+        }
+        assert!(matches!(
+            tree.expected_value(),
+            Err(Error::MalformedTree { .. })
+        ));
+    }
+}
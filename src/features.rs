@@ -0,0 +1,361 @@
+//! Predictions from features keyed by name instead of position.
+//!
+//! Features come either as `(name, value)` pairs, e.g. from a `HashMap`, or as the fields of a
+//! struct implementing [`NamedFeatures`], which `silva-macros` can derive.
+
+use std::{collections::HashMap, hash::BuildHasher};
+
+use ordered_float::NotNan;
+use rustc_hash::FxHashMap;
+use thiserror::Error;
+
+use crate::{Error, Float, MultiOutputForest};
+
+/// Custom error types for name-keyed features
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum FeatureError {
+    #[error("The model has no feature names")]
+    MissingFeatureNames,
+    #[error("Unknown feature: {name}")]
+    UnknownFeature { name: String },
+    #[error("The model has {names} feature names for {n_features} features")]
+    NameCount { names: usize, n_features: usize },
+}
+
+/// A value that can be fed to a model as a feature.
+pub trait FeatureValue {
+    /// The value as `f64`, NaN when missing.
+    fn to_f64(&self) -> f64;
+}
+
+macro_rules! impl_feature_value {
+    ($($ty:ty),*) => {
+        $(
+            impl FeatureValue for $ty {
+                fn to_f64(&self) -> f64 {
+                    *self as f64
+                }
+            }
+        )*
+    };
+}
+
+impl_feature_value!(f64, f32, i8, i16, i32, i64, u8, u16, u32, u64, isize, usize);
+
+impl FeatureValue for bool {
+    fn to_f64(&self) -> f64 {
+        if *self { 1.0 } else { 0.0 }
+    }
+}
+
+/// `None` is a missing value.
+impl<T: FeatureValue> FeatureValue for Option<T> {
+    fn to_f64(&self) -> f64 {
+        self.as_ref().map_or(f64::NAN, T::to_f64)
+    }
+}
+
+/// A record whose fields are named features.
+///
+/// Derive it with `#[derive(silva_macros::NamedFeatures)]`: every field becomes a feature named
+/// after it, `#[silva(rename = "...")]` changes the name and `#[silva(skip)]` leaves a field
+/// out. Field types must implement [`FeatureValue`].
+pub trait NamedFeatures {
+    /// Call `visit` with the name and value of every feature.
+    fn visit_features(&self, visit: &mut dyn FnMut(&str, f64));
+}
+
+/// The category codes a model was trained with, for categorical features given as strings.
+///
+/// Codes of string columns are not stable across data frames or batches (they depend on the
+/// order categories were first seen), so they have to be supplied from training.
+#[derive(Debug, Clone, Default)]
+pub struct CategoryCodes {
+    features: FxHashMap<String, FxHashMap<String, usize>>,
+}
+
+impl CategoryCodes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Code the categories of `feature` by their position in `categories`.
+    pub fn with_feature(
+        mut self,
+        feature: impl Into<String>,
+        categories: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        let codes = categories
+            .into_iter()
+            .enumerate()
+            .map(|(code, category)| (category.into(), code))
+            .collect();
+        self.features.insert(feature.into(), codes);
+        self
+    }
+
+    /// Codes of the categories of `feature`, if it is categorical.
+    #[cfg(any(feature = "arrow", feature = "polars"))]
+    pub(crate) fn of(&self, feature: &str) -> Option<&FxHashMap<String, usize>> {
+        self.features.get(feature)
+    }
+}
+
+/// Resolves feature names to their positions in the input row, once per model.
+///
+/// Features absent from the input are missing values (NaN), names unknown to the model are
+/// errors.
+#[derive(Debug, Clone)]
+pub struct FeatureMapper {
+    indices: FxHashMap<String, usize>,
+    n_features: usize,
+}
+
+impl FeatureMapper {
+    pub fn new(feature_names: &[String]) -> Self {
+        Self {
+            indices: feature_names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (name.clone(), i))
+                .collect(),
+            n_features: feature_names.len(),
+        }
+    }
+
+    /// Mapper over the feature names stored in the model metadata, which must name every feature
+    /// when the metadata also gives their number, so that rows have the length the model checks.
+    pub fn from_model<F: Float>(model: &MultiOutputForest<F>) -> Result<Self, FeatureError> {
+        let metadata = model.metadata();
+        let names = metadata.feature_names.len();
+        if names == 0 {
+            return Err(FeatureError::MissingFeatureNames);
+        }
+        if let Some(n_features) = metadata.n_features
+            && n_features != names
+        {
+            return Err(FeatureError::NameCount { names, n_features });
+        }
+        Ok(Self::new(&metadata.feature_names))
+    }
+
+    pub fn n_features(&self) -> usize {
+        self.n_features
+    }
+
+    pub fn index(&self, name: &str) -> Result<usize, FeatureError> {
+        self.indices
+            .get(name)
+            .copied()
+            .ok_or_else(|| FeatureError::UnknownFeature {
+                name: name.to_string(),
+            })
+    }
+
+    /// Write the named features into `row`, reusing its allocation.
+    pub fn fill_row<'a, F: Float>(
+        &self,
+        features: impl IntoIterator<Item = (&'a str, F)>,
+        row: &mut Vec<F>,
+    ) -> Result<(), FeatureError> {
+        row.clear();
+        row.resize(self.n_features, F::nan());
+        for (name, value) in features {
+            row[self.index(name)?] = value;
+        }
+        Ok(())
+    }
+
+    /// Dense input row of the named features.
+    pub fn row<'a, F: Float>(
+        &self,
+        features: impl IntoIterator<Item = (&'a str, F)>,
+    ) -> Result<Vec<F>, FeatureError> {
+        let mut row = Vec::with_capacity(self.n_features);
+        self.fill_row(features, &mut row)?;
+        Ok(row)
+    }
+
+    /// Dense input row of the fields of `record`, see [`Self::row`].
+    pub fn record_row<F: Float>(
+        &self,
+        record: &impl NamedFeatures,
+    ) -> Result<Vec<F>, FeatureError> {
+        let mut row = vec![F::nan(); self.n_features];
+        let mut result = Ok(());
+        record.visit_features(&mut |name, value| {
+            if result.is_ok() {
+                result = self.index(name).map(|i| row[i] = F::from_f64(value));
+            }
+        });
+        result.map(|()| row)
+    }
+
+    /// Predict from named features with [`MultiOutputForest::try_predict`], so that missing
+    /// values at splits without a default direction are errors too.
+    pub fn predict<'a, F: Float>(
+        &self,
+        model: &MultiOutputForest<F>,
+        features: impl IntoIterator<Item = (&'a str, F)>,
+    ) -> Result<Vec<NotNan<F>>, Error> {
+        model.try_predict(&self.row(features)?)
+    }
+
+    /// Predict from the fields of `record`, see [`Self::predict`].
+    pub fn predict_record<F: Float>(
+        &self,
+        model: &MultiOutputForest<F>,
+        record: &impl NamedFeatures,
+    ) -> Result<Vec<NotNan<F>>, Error> {
+        model.try_predict(&self.record_row(record)?)
+    }
+}
+
+impl<F: Float> MultiOutputForest<F> {
+    /// Predict from features keyed by name, see [`FeatureMapper`].
+    ///
+    /// This resolves the feature names on every call; build a [`FeatureMapper`] once to predict
+    /// many rows.
+    pub fn predict_named<S: BuildHasher>(
+        &self,
+        features: &HashMap<String, F, S>,
+    ) -> Result<Vec<NotNan<F>>, Error> {
+        FeatureMapper::from_model(self)?.predict(
+            self,
+            features.iter().map(|(name, &value)| (name.as_str(), value)),
+        )
+    }
+
+    /// Predict from the fields of `record`, see [`NamedFeatures`].
+    ///
+    /// This resolves the feature names on every call, like [`Self::predict_named`].
+    pub fn predict_record(&self, record: &impl NamedFeatures) -> Result<Vec<NotNan<F>>, Error> {
+        FeatureMapper::from_model(self)?.predict_record(self, record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::parser::{read_lightgbm_model, read_xgboost_model, test_utils::read_features};
+
+    #[test]
+    fn test_predict_named() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/lightgbm/multiclass_classification");
+        let model = read_lightgbm_model(data_dir.join("model.txt")).unwrap();
+        let mapper = FeatureMapper::from_model(&model).unwrap();
+
+        for x in read_features(&data_dir.join("X.csv")) {
+            // reversed order, to make sure positions come from the names
+            let features: HashMap<String, f64> = x
+                .iter()
+                .enumerate()
+                .rev()
+                .map(|(i, &v)| (format!("Column_{i}"), v))
+                .collect();
+            assert_eq!(model.predict_named(&features).unwrap(), model.predict(&x));
+            let pairs = features.iter().map(|(name, &v)| (name.as_str(), v));
+            assert_eq!(mapper.predict(&model, pairs).unwrap(), model.predict(&x));
+        }
+    }
+
+    #[test]
+    fn test_absent_and_unknown_features() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let model =
+            read_lightgbm_model(root.join("test_data/lightgbm/regression/model.txt")).unwrap();
+        let mapper = FeatureMapper::from_model(&model).unwrap();
+
+        let row: Vec<f64> = mapper.row([("Column_1", 0.5)]).unwrap();
+        assert_eq!(row.len(), mapper.n_features());
+        assert_eq!(row[1], 0.5);
+        assert!(row.iter().enumerate().all(|(i, v)| i == 1 || v.is_nan()));
+
+        assert_eq!(
+            mapper.row([("Column_1", 0.5), ("age", 1.0)]),
+            Err(FeatureError::UnknownFeature {
+                name: "age".to_string()
+            })
+        );
+
+        let mut model =
+            read_xgboost_model(root.join("test_data/xgboost/regression/model.json")).unwrap();
+        assert!(matches!(
+            model.predict_named(&HashMap::new()),
+            Err(Error::Feature(FeatureError::MissingFeatureNames))
+        ));
+
+        // names must cover every feature the model counts
+        let n_features = model.n_features();
+        model.metadata_mut().feature_names = vec!["f0".to_string()];
+        assert_eq!(model.metadata().n_features, Some(n_features));
+        assert_eq!(
+            FeatureMapper::from_model(&model).unwrap_err(),
+            FeatureError::NameCount {
+                names: 1,
+                n_features
+            }
+        );
+    }
+
+    #[test]
+    fn test_absent_feature_without_default_direction() {
+        // the native multiclass model has no default directions, so absent features cannot be
+        // routed and must give an error rather than a panic
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let mut model = MultiOutputForest::<f64>::from_file(
+            root.join("test_data/native/multiclass_classification/model.json"),
+        )
+        .unwrap();
+        let names: Vec<String> = (0..model.n_features()).map(|i| format!("f{i}")).collect();
+        model.metadata_mut().feature_names = names.clone();
+        let mapper = FeatureMapper::new(&names);
+        assert!(matches!(
+            mapper.predict(&model, [("f0", 0.5)]),
+            Err(Error::MissingValue { .. })
+        ));
+        assert!(matches!(
+            model.predict_named(&HashMap::new()),
+            Err(Error::MissingValue { .. })
+        ));
+    }
+
+    struct Applicant {
+        age: u32,
+        income: Option<f64>,
+        employed: bool,
+    }
+
+    impl NamedFeatures for Applicant {
+        fn visit_features(&self, visit: &mut dyn FnMut(&str, f64)) {
+            visit("age", self.age.to_f64());
+            visit("income", self.income.to_f64());
+            visit("employed", self.employed.to_f64());
+        }
+    }
+
+    #[test]
+    fn test_record() {
+        let names = ["income", "age", "employed", "score"].map(String::from);
+        let mapper = FeatureMapper::new(&names);
+        let applicant = Applicant {
+            age: 42,
+            income: None,
+            employed: true,
+        };
+        let row: Vec<f64> = mapper.record_row(&applicant).unwrap();
+        assert!(row[0].is_nan() && row[3].is_nan());
+        assert_eq!(&row[1..3], &[42.0, 1.0]);
+
+        let mapper = FeatureMapper::new(&names[..2]);
+        assert_eq!(
+            mapper.record_row::<f64>(&applicant),
+            Err(FeatureError::UnknownFeature {
+                name: "employed".to_string()
+            })
+        );
+    }
+}
@@ -0,0 +1,45 @@
+use std::{
+    fmt::{Debug, Display},
+    iter::Sum,
+};
+
+use bytemuck::Pod;
+use ordered_float::FloatCore;
+use serde::{Serialize, de::DeserializeOwned};
+
+/// Floating point precision of model parameters and inputs, implemented for `f32` and `f64`.
+pub trait Float:
+    FloatCore
+    + Into<f64>
+    + Default
+    + Debug
+    + Display
+    + Sum
+    + Pod
+    + Send
+    + Sync
+    + Serialize
+    + DeserializeOwned
+    + 'static
+{
+    /// Convert from `f64`, rounding to the nearest representable value.
+    fn from_f64(v: f64) -> Self;
+}
+
+/// Bound on the rounding error of summing `terms` values of `F` whose magnitudes add up to at most
+/// `magnitude`, in any order.
+pub(crate) fn summation_error<F: Float>(magnitude: f64, terms: usize) -> f64 {
+    magnitude * terms as f64 * F::epsilon().into()
+}
+
+impl Float for f32 {
+    fn from_f64(v: f64) -> Self {
+        v as f32
+    }
+}
+
+impl Float for f64 {
+    fn from_f64(v: f64) -> Self {
+        v
+    }
+}
@@ -1,56 +1,198 @@
+use std::sync::OnceLock;
+
 use ordered_float::NotNan;
 use serde::{Deserialize, Serialize};
 
-use crate::tree::Tree;
+use crate::{
+    decision::RemainingBounds,
+    error::Error,
+    float::Float,
+    metadata::ModelMetadata,
+    tree::{LeafNumbering, Tree, TreeNode},
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Forest {
-    base_value: f64,
-    trees: Vec<Tree>,
+#[serde(bound = "")]
+pub struct Forest<F: Float = f64> {
+    pub(crate) base_value: F,
+    pub(crate) trees: Vec<Tree<F>>,
+    /// Bounds of the sum of the trees from each position on, computed on first use by
+    /// [`Forest::decide`].
+    #[serde(skip)]
+    pub(crate) remaining_bounds: OnceLock<Vec<RemainingBounds>>,
 }
 
-impl Forest {
-    pub fn new(base_value: f64, trees: Vec<Tree>) -> Self {
-        Self { base_value, trees }
+impl<F: Float> Forest<F> {
+    pub fn new(base_value: F, trees: Vec<Tree<F>>) -> Self {
+        Self {
+            base_value,
+            trees,
+            remaining_bounds: OnceLock::new(),
+        }
+    }
+
+    /// Convert the forest to another floating point precision.
+    pub fn cast<G: Float>(&self) -> Forest<G> {
+        Forest {
+            base_value: G::from_f64(self.base_value.into()),
+            trees: self.trees.iter().map(Tree::cast).collect(),
+            remaining_bounds: OnceLock::new(),
+        }
+    }
+
+    pub fn predict(&self, x: &[F]) -> NotNan<F> {
+        self.predict_by(|index| x[index])
+    }
+
+    /// Number of features the splits refer to, see [`Tree::n_features`].
+    pub fn n_features(&self) -> usize {
+        self.trees.iter().map(Tree::n_features).max().unwrap_or(0)
     }
 
-    pub fn predict(&self, x: &[f64]) -> NotNan<f64> {
-        let predictions: Vec<f64> = self
+    /// Fallible [`Self::predict`], see [`Tree::try_predict`].
+    pub fn try_predict(&self, x: &[F]) -> Result<NotNan<F>, Error> {
+        self.try_predict_by(|node| node.try_feature(x))
+    }
+
+    pub(crate) fn try_predict_by(
+        &self,
+        feature: impl Fn(&TreeNode<F>) -> Result<F, Error> + Copy,
+    ) -> Result<NotNan<F>, Error> {
+        let predictions = self
             .trees
             .iter()
-            .map(|tree| tree.predict(x).into_inner())
+            .map(|tree| tree.try_predict_by(feature).map(NotNan::into_inner))
+            .collect::<Result<Vec<F>, _>>()?;
+
+        let res = self.base_value + predictions.iter().copied().sum::<F>();
+
+        NotNan::new(res)
+            .ok()
+            .filter(|res| res.is_finite())
+            .ok_or(Error::NonFiniteOutput)
+    }
+
+    /// Leaf reached by `x` in every tree, see [`Tree::predict_leaf`].
+    pub fn predict_leaf(&self, x: &[F], numbering: LeafNumbering) -> Vec<usize> {
+        self.trees
+            .iter()
+            .map(|tree| tree.predict_leaf(x, numbering))
+            .collect()
+    }
+
+    pub(crate) fn predict_by(&self, feature: impl Fn(usize) -> F + Copy) -> NotNan<F> {
+        let predictions: Vec<F> = self
+            .trees
+            .iter()
+            .map(|tree| tree.predict_by(feature).into_inner())
             .collect();
 
-        let res = self.base_value + predictions.iter().sum::<f64>();
+        let res = self.base_value + predictions.iter().copied().sum::<F>();
 
         NotNan::new(res).unwrap()
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MultiOutputForest {
-    forests: Vec<Forest>,
+/// Serialized with a format version, see [`crate::NATIVE_FORMAT_VERSION`].
+#[derive(Debug, Clone)]
+pub struct MultiOutputForest<F: Float = f64> {
+    pub(crate) forests: Vec<Forest<F>>,
+    pub(crate) metadata: ModelMetadata,
 }
 
-impl MultiOutputForest {
-    pub fn new(forests: Vec<Forest>) -> Self {
-        Self { forests }
+impl<F: Float> MultiOutputForest<F> {
+    pub fn new(forests: Vec<Forest<F>>) -> Self {
+        Self {
+            forests,
+            metadata: ModelMetadata::default(),
+        }
+    }
+
+    pub fn with_metadata(mut self, metadata: ModelMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn metadata(&self) -> &ModelMetadata {
+        &self.metadata
+    }
+
+    pub fn metadata_mut(&mut self) -> &mut ModelMetadata {
+        &mut self.metadata
+    }
+
+    /// Convert the model to another floating point precision, e.g. `model.cast::<f32>()`.
+    pub fn cast<G: Float>(&self) -> MultiOutputForest<G> {
+        MultiOutputForest {
+            forests: self.forests.iter().map(Forest::cast).collect(),
+            metadata: self.metadata.clone(),
+        }
+    }
+
+    /// Number of input features, as stored by the source library, or the number the splits
+    /// refer to when the model does not store it.
+    pub fn n_features(&self) -> usize {
+        self.metadata.n_features.unwrap_or_else(|| {
+            self.forests
+                .iter()
+                .map(Forest::n_features)
+                .max()
+                .unwrap_or(0)
+        })
+    }
+
+    /// Fallible [`Self::predict`]: when the model stores its number of features, `x` must have
+    /// exactly that length. See [`Tree::try_predict`] for the other checks.
+    pub fn try_predict(&self, x: &[F]) -> Result<Vec<NotNan<F>>, Error> {
+        if let Some(expected) = self.metadata.n_features
+            && x.len() != expected
+        {
+            return Err(Error::FeatureCount {
+                expected,
+                actual: x.len(),
+            });
+        }
+        self.forests
+            .iter()
+            .map(|forest| forest.try_predict(x))
+            .collect()
     }
 
-    pub fn predict(&self, x: &[f64]) -> Vec<NotNan<f64>> {
+    pub fn predict(&self, x: &[F]) -> Vec<NotNan<F>> {
         self.forests
             .iter()
             .map(|forest| forest.predict(x))
             .collect()
     }
+
+    /// Leaf reached by `x` in every tree of every output, numbered like the library the model was
+    /// converted from does with `pred_leaf=True` (see [`LeafNumbering::of`]). Fails with
+    /// [`Error::UnknownLeafNumbering`] when the metadata does not record that library, use
+    /// [`Self::predict_leaf_with`] then.
+    ///
+    /// XGBoost and LightGBM return a flat row instead, interleaving the outputs tree by tree.
+    pub fn predict_leaf(&self, x: &[F]) -> Result<Vec<Vec<usize>>, Error> {
+        let numbering = LeafNumbering::of(&self.metadata).ok_or(Error::UnknownLeafNumbering)?;
+        Ok(self.predict_leaf_with(x, numbering))
+    }
+
+    /// [`Self::predict_leaf`] with an explicit numbering.
+    pub fn predict_leaf_with(&self, x: &[F], numbering: LeafNumbering) -> Vec<Vec<usize>> {
+        self.forests
+            .iter()
+            .map(|forest| forest.predict_leaf(x, numbering))
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use ordered_float::NotNan;
 
+    use std::path::PathBuf;
+
     use crate::{
-        map::FxIndexMap,
+        parser::{read_xgboost_model, test_utils::read_features},
         tree::{Tree, TreeNode},
     };
 
@@ -58,123 +200,20 @@ mod tests {
 
     #[test]
     fn test_forest_predict() {
-        // First tree
-        let mut nodes1 = FxIndexMap::default();
-        nodes1.insert(
-            0,
-            TreeNode {
-                id: 0,
-                split_index: 0,
-                split_condition: NotNan::new(5.0).unwrap(),
-                left: Some(1),
-                right: Some(2),
-                value: NotNan::new(0.0).unwrap(),
-            },
-        );
-        nodes1.insert(
-            1,
-            TreeNode {
-                id: 1,
-                split_index: 1,
-                split_condition: NotNan::new(3.0).unwrap(),
-                left: Some(3),
-                right: Some(4),
-                value: NotNan::new(0.0).unwrap(),
-            },
-        );
-        nodes1.insert(
-            2,
-            TreeNode {
-                id: 2,
-                split_index: 1,
-                split_condition: NotNan::new(2.0).unwrap(),
-                left: Some(5),
-                right: Some(6),
-                value: NotNan::new(0.0).unwrap(),
-            },
-        );
-        nodes1.insert(
-            3,
-            TreeNode {
-                id: 3,
-                split_index: 0,
-                split_condition: NotNan::new(0.0).unwrap(),
-                left: None,
-                right: None,
-                value: NotNan::new(3.0).unwrap(),
-            },
-        );
-        nodes1.insert(
-            4,
-            TreeNode {
-                id: 4,
-                split_index: 0,
-                split_condition: NotNan::new(0.0).unwrap(),
-                left: None,
-                right: None,
-                value: NotNan::new(4.0).unwrap(),
-            },
-        );
-        nodes1.insert(
-            5,
-            TreeNode {
-                id: 5,
-                split_index: 0,
-                split_condition: NotNan::new(0.0).unwrap(),
-                left: None,
-                right: None,
-                value: NotNan::new(5.0).unwrap(),
-            },
-        );
-        nodes1.insert(
-            6,
-            TreeNode {
-                id: 6,
-                split_index: 0,
-                split_condition: NotNan::new(0.0).unwrap(),
-                left: None,
-                right: None,
-                value: NotNan::new(6.0).unwrap(),
-            },
-        );
-        let tree1 = Tree::new(nodes1, 0);
-
-        // Second tree
-        let mut nodes2 = FxIndexMap::default();
-        nodes2.insert(
-            0,
-            TreeNode {
-                id: 0,
-                split_index: 0,
-                split_condition: NotNan::new(5.0).unwrap(),
-                left: Some(1),
-                right: Some(2),
-                value: NotNan::new(0.0).unwrap(),
-            },
-        );
-        nodes2.insert(
-            1,
-            TreeNode {
-                id: 1,
-                split_index: 0,
-                split_condition: NotNan::new(2.0).unwrap(),
-                left: None,
-                right: None,
-                value: NotNan::new(10.0).unwrap(),
-            },
-        );
-        nodes2.insert(
-            2,
-            TreeNode {
-                id: 2,
-                split_index: 0,
-                split_condition: NotNan::new(3.0).unwrap(),
-                left: None,
-                right: None,
-                value: NotNan::new(20.0).unwrap(),
-            },
-        );
-        let tree2 = Tree::new(nodes2, 0);
+        let tree1 = Tree::from_nodes(vec![
+            TreeNode::split(0, 0, 5.0, 1, 2),
+            TreeNode::split(1, 1, 3.0, 3, 4),
+            TreeNode::split(2, 1, 2.0, 5, 6),
+            TreeNode::leaf(3, 3.0),
+            TreeNode::leaf(4, 4.0),
+            TreeNode::leaf(5, 5.0),
+            TreeNode::leaf(6, 6.0),
+        ]);
+        let tree2 = Tree::from_nodes(vec![
+            TreeNode::split(0, 0, 5.0, 1, 2),
+            TreeNode::leaf(1, 10.0),
+            TreeNode::leaf(2, 20.0),
+        ]);
 
         let forest = Forest::new(100.0, vec![tree1, tree2]);
 
@@ -183,4 +222,73 @@ mod tests {
         assert_eq!(forest.predict(&[6.0, 1.0]), NotNan::new(125.0).unwrap());
         assert_eq!(forest.predict(&[6.0, 3.0]), NotNan::new(126.0).unwrap());
     }
+
+    #[test]
+    fn test_try_predict() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/xgboost/multiclass_classification");
+        let mut model = read_xgboost_model(data_dir.join("model.json")).unwrap();
+        assert_eq!(model.n_features(), 5);
+        for x in read_features(&data_dir.join("X.csv")) {
+            assert_eq!(model.try_predict(&x).unwrap(), model.predict(&x));
+            assert!(matches!(
+                model.try_predict(&x[..4]),
+                Err(Error::FeatureCount {
+                    expected: 5,
+                    actual: 4
+                })
+            ));
+        }
+
+        // without a stored count, only features used by splits are required
+        let x = [0.0; 8];
+        assert!(model.try_predict(&x).is_err());
+        model.metadata_mut().n_features = None;
+        let used = model.n_features();
+        assert!(used <= 5);
+        assert!(model.try_predict(&x).is_ok());
+        assert!(matches!(
+            model.try_predict(&x[..used - 1]),
+            Err(Error::FeatureIndex { feature, actual, .. }) if feature == used - 1 && actual == used - 1
+        ));
+
+        // a split beyond the stored count is reported by its feature index
+        model.metadata_mut().n_features = Some(used - 1);
+        assert!(matches!(
+            model.try_predict(&x[..used - 1]),
+            Err(Error::FeatureIndex { feature, .. }) if feature == used - 1
+        ));
+    }
+
+    #[test]
+    fn test_predict_leaf_unknown_source() {
+        // the native model records no source library, so its numbering must be explicit
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/native/multiclass_classification");
+        let model = MultiOutputForest::<f64>::from_file(data_dir.join("model.json")).unwrap();
+        let x = &read_features(&data_dir.join("X.csv"))[0];
+        assert!(matches!(
+            model.predict_leaf(x),
+            Err(Error::UnknownLeafNumbering)
+        ));
+        let leaves = model.predict_leaf_with(x, LeafNumbering::LeafIndex);
+        for (forest, leaves) in model.forests.iter().zip(&leaves) {
+            assert_eq!(forest.predict_leaf(x, LeafNumbering::LeafIndex), *leaves);
+        }
+    }
+
+    #[test]
+    fn test_try_predict_non_finite() {
+        let tree = |value| Tree::from_nodes(vec![TreeNode::leaf(0, value)]);
+        let forest = Forest::new(0.0, vec![tree(f64::INFINITY), tree(f64::NEG_INFINITY)]);
+        assert!(matches!(
+            forest.try_predict(&[]),
+            Err(Error::NonFiniteOutput)
+        ));
+        let forest = Forest::new(0.0, vec![tree(f64::MAX), tree(f64::MAX)]);
+        assert!(matches!(
+            forest.try_predict(&[]),
+            Err(Error::NonFiniteOutput)
+        ));
+    }
 }
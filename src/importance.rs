@@ -0,0 +1,239 @@
+//! Global feature importance computed from the split statistics of the trees.
+
+use std::collections::BTreeMap;
+
+use crate::{Error, Float, Forest, MultiOutputForest};
+
+/// How the splits on a feature are scored, following XGBoost's `importance_type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportanceType {
+    /// Number of splits on the feature.
+    Weight,
+    /// Average loss reduction of the splits on the feature.
+    Gain,
+    /// Average hessian sum of the splits on the feature.
+    Cover,
+    /// Total loss reduction of the splits on the feature.
+    TotalGain,
+    /// Total hessian sum of the splits on the feature.
+    TotalCover,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct SplitStatistics {
+    count: usize,
+    total_gain: f64,
+    total_cover: f64,
+}
+
+impl SplitStatistics {
+    fn score(&self, kind: ImportanceType) -> f64 {
+        match kind {
+            ImportanceType::Weight => self.count as f64,
+            ImportanceType::Gain => self.total_gain / self.count as f64,
+            ImportanceType::Cover => self.total_cover / self.count as f64,
+            ImportanceType::TotalGain => self.total_gain,
+            ImportanceType::TotalCover => self.total_cover,
+        }
+    }
+}
+
+impl<F: Float> Forest<F> {
+    /// Add the statistics of every split to `statistics`, indexed by feature.
+    fn add_split_statistics(
+        &self,
+        kind: ImportanceType,
+        statistics: &mut BTreeMap<usize, SplitStatistics>,
+    ) -> Result<(), Error> {
+        let needs_gain = matches!(kind, ImportanceType::Gain | ImportanceType::TotalGain);
+        let needs_cover = matches!(kind, ImportanceType::Cover | ImportanceType::TotalCover);
+        let splits = self
+            .trees
+            .iter()
+            .flat_map(|tree| tree.node_map.values())
+            .filter(|node| !node.is_leaf());
+        for node in splits {
+            let entry = statistics.entry(node.split_index).or_default();
+            entry.count += 1;
+            if needs_gain {
+                let gain = node
+                    .gain
+                    .ok_or(Error::MissingStatistic { statistic: "gain" })?;
+                entry.total_gain += gain.into_inner().into();
+            }
+            if needs_cover {
+                // XGBoost's cover is the hessian sum, and its nodes record no separate one
+                let hessian = node.hessian.or(node.cover).ok_or(Error::MissingStatistic {
+                    statistic: "hessian",
+                })?;
+                entry.total_cover += hessian.into_inner().into();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<F: Float> MultiOutputForest<F> {
+    /// Name of feature `index` in importance reports: its feature name, or `f{index}` like
+    /// XGBoost when the model has none.
+    fn importance_key(&self, index: usize) -> String {
+        self.metadata
+            .feature_names
+            .get(index)
+            .cloned()
+            .unwrap_or_else(|| format!("f{index}"))
+    }
+
+    fn importance_of<'a>(
+        &self,
+        forests: impl IntoIterator<Item = &'a Forest<F>>,
+        kind: ImportanceType,
+    ) -> Result<BTreeMap<String, f64>, Error> {
+        let mut statistics = BTreeMap::new();
+        for forest in forests {
+            forest.add_split_statistics(kind, &mut statistics)?;
+        }
+        Ok(statistics
+            .into_iter()
+            .map(|(index, statistics)| (self.importance_key(index), statistics.score(kind)))
+            .collect())
+    }
+
+    /// Importance of every feature used by a split, over all outputs. Features without any
+    /// split are left out, as XGBoost's `get_score` does.
+    ///
+    /// Gains are XGBoost's `loss_changes` and LightGBM's `split_gain`, covers are hessian sums
+    /// (`sum_hessian` and `internal_weight`): the node hessian, or the node cover for models that
+    /// record no separate hessian, like XGBoost. Models without them return
+    /// [`Error::MissingStatistic`] for the gain and cover modes.
+    pub fn feature_importance(&self, kind: ImportanceType) -> Result<BTreeMap<String, f64>, Error> {
+        self.importance_of(&self.forests, kind)
+    }
+
+    /// Importance of the features for every output separately, see
+    /// [`Self::feature_importance`].
+    pub fn feature_importance_per_output(
+        &self,
+        kind: ImportanceType,
+    ) -> Result<Vec<BTreeMap<String, f64>>, Error> {
+        self.forests
+            .iter()
+            .map(|forest| self.importance_of([forest], kind))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::parser::{read_lightgbm_model, read_xgboost_model};
+
+    #[test]
+    fn test_xgboost_importance() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let model_path = root.join("test_data/xgboost/multiclass_classification/model.json");
+        let model = read_xgboost_model(&model_path).unwrap();
+        let record: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&model_path).unwrap()).unwrap();
+
+        // the hessian sum is kept once, as the cover
+        let nodes = model.forests[0].trees[0].node_map.values();
+        assert!(nodes.clone().all(|node| node.hessian().is_none()));
+        assert!(nodes.clone().all(|node| node.cover().is_some()));
+
+        // totals straight from the node arrays of the model file
+        let mut expected: BTreeMap<String, SplitStatistics> = BTreeMap::new();
+        let trees = record["learner"]["gradient_booster"]["model"]["trees"]
+            .as_array()
+            .unwrap();
+        for tree in trees {
+            for (i, left) in tree["left_children"].as_array().unwrap().iter().enumerate() {
+                if left.as_i64() == Some(-1) {
+                    continue;
+                }
+                let feature = tree["split_indices"][i].as_u64().unwrap();
+                let entry = expected.entry(format!("f{feature}")).or_default();
+                entry.count += 1;
+                entry.total_gain += tree["loss_changes"][i].as_f64().unwrap();
+                entry.total_cover += tree["sum_hessian"][i].as_f64().unwrap();
+            }
+        }
+
+        for kind in [
+            ImportanceType::Weight,
+            ImportanceType::Gain,
+            ImportanceType::Cover,
+            ImportanceType::TotalGain,
+            ImportanceType::TotalCover,
+        ] {
+            let importance = model.feature_importance(kind).unwrap();
+            assert_eq!(importance.len(), expected.len());
+            for (name, statistics) in &expected {
+                let expected = statistics.score(kind);
+                assert!((importance[name] - expected).abs() <= 1e-9 * expected.abs());
+            }
+
+            // outputs add up to the aggregate for totals
+            let per_output = model.feature_importance_per_output(kind).unwrap();
+            assert_eq!(per_output.len(), 3);
+            if matches!(kind, ImportanceType::Weight | ImportanceType::TotalGain) {
+                for (name, total) in &importance {
+                    let sum: f64 = per_output.iter().filter_map(|o| o.get(name)).sum();
+                    assert!((sum - total).abs() <= 1e-9 * total.abs());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_lightgbm_importance() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let model_path = root.join("test_data/lightgbm/binary_classification/model.txt");
+        let model = read_lightgbm_model(&model_path).unwrap();
+        let text = std::fs::read_to_string(&model_path).unwrap();
+
+        // LightGBM's `feature_importance` with `split` and `gain` from the tree sections
+        let values = |key: &str| -> Vec<Vec<f64>> {
+            text.lines()
+                .filter_map(|line| line.strip_prefix(key))
+                .map(|values| values.split(' ').map(|v| v.parse().unwrap()).collect())
+                .collect()
+        };
+        let mut splits: BTreeMap<String, (f64, f64)> = BTreeMap::new();
+        for (features, gains) in values("split_feature=").iter().zip(values("split_gain=")) {
+            for (feature, gain) in features.iter().zip(gains) {
+                let entry = splits.entry(format!("Column_{feature}")).or_default();
+                entry.0 += 1.0;
+                entry.1 += gain;
+            }
+        }
+
+        let weight = model.feature_importance(ImportanceType::Weight).unwrap();
+        let total_gain = model.feature_importance(ImportanceType::TotalGain).unwrap();
+        assert_eq!(weight.len(), splits.len());
+        for (name, (count, gain)) in &splits {
+            assert_eq!(weight[name], *count);
+            assert!((total_gain[name] - gain).abs() <= 1e-9 * gain.abs());
+        }
+        assert!(model.feature_importance(ImportanceType::Cover).is_ok());
+    }
+
+    #[test]
+    fn test_missing_gains() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let model_path = root.join("test_data/native/multiclass_classification/model.json");
+        let model = MultiOutputForest::<f64>::from_file(model_path).unwrap();
+        assert!(
+            !model
+                .feature_importance(ImportanceType::Weight)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(matches!(
+            model.feature_importance(ImportanceType::TotalGain),
+            Err(Error::MissingStatistic { statistic: "gain" })
+        ));
+    }
+}
@@ -0,0 +1,179 @@
+//! Prediction with the trees of some boosting iterations only, like XGBoost's
+//! `iteration_range` and LightGBM's `num_iteration`.
+
+use std::ops::Range;
+
+use ordered_float::NotNan;
+
+use crate::{Float, Forest, MultiOutputForest};
+
+impl<F: Float> Forest<F> {
+    /// Sum of the base value and the trees of the iterations in `iterations`.
+    fn predict_iterations(
+        &self,
+        x: &[F],
+        iterations: Range<usize>,
+        trees_per_iteration: usize,
+    ) -> NotNan<F> {
+        let end = (iterations.end * trees_per_iteration).min(self.trees.len());
+        let start = (iterations.start * trees_per_iteration).min(end);
+        let predictions: Vec<F> = self.trees[start..end]
+            .iter()
+            .map(|tree| tree.predict(x).into_inner())
+            .collect();
+
+        let res = self.base_value + predictions.iter().copied().sum::<F>();
+
+        NotNan::new(res).unwrap()
+    }
+
+    /// Predictions after every iteration, see [`Forest::staged_predict`].
+    fn staged_predict_iterations<'a>(
+        &'a self,
+        x: &'a [F],
+        trees_per_iteration: usize,
+    ) -> impl Iterator<Item = NotNan<F>> + 'a {
+        self.trees
+            .chunks(trees_per_iteration)
+            .scan(F::from_f64(0.0), move |sum, trees| {
+                for tree in trees {
+                    *sum = *sum + tree.predict(x).into_inner();
+                }
+                Some(NotNan::new(self.base_value + *sum).unwrap())
+            })
+    }
+
+    /// Predict with the trees of the boosting iterations in `iterations` only, one tree per
+    /// iteration. The base value is always included, so an empty range gives the base value.
+    pub fn predict_iteration_range(&self, x: &[F], iterations: Range<usize>) -> NotNan<F> {
+        self.predict_iterations(x, iterations, 1)
+    }
+
+    /// Prediction after each boosting iteration, the last one being [`Forest::predict`].
+    pub fn staged_predict<'a>(&'a self, x: &'a [F]) -> impl Iterator<Item = NotNan<F>> + 'a {
+        self.staged_predict_iterations(x, 1)
+    }
+}
+
+impl<F: Float> MultiOutputForest<F> {
+    fn trees_per_iteration(&self) -> usize {
+        self.metadata.trees_per_iteration.unwrap_or(1).max(1)
+    }
+
+    /// Number of boosting iterations of the model.
+    pub fn n_iterations(&self) -> usize {
+        let trees_per_iteration = self.trees_per_iteration();
+        self.forests
+            .iter()
+            .map(|forest| forest.trees.len().div_ceil(trees_per_iteration))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Predict with the trees of the boosting iterations in `iterations` only, like XGBoost's
+    /// `iteration_range`. An iteration adds `trees_per_iteration` trees (see
+    /// [`ModelMetadata`](crate::ModelMetadata)) to every output.
+    pub fn predict_iteration_range(&self, x: &[F], iterations: Range<usize>) -> Vec<NotNan<F>> {
+        let trees_per_iteration = self.trees_per_iteration();
+        self.forests
+            .iter()
+            .map(|forest| forest.predict_iterations(x, iterations.clone(), trees_per_iteration))
+            .collect()
+    }
+
+    /// Predict with the iterations up to the best one found by early stopping when the model
+    /// records it, and with all trees otherwise.
+    pub fn predict_best_iteration(&self, x: &[F]) -> Vec<NotNan<F>> {
+        match self.metadata.best_iteration {
+            Some(best) => self.predict_iteration_range(x, 0..best + 1),
+            None => self.predict(x),
+        }
+    }
+
+    /// Predictions of every output after each boosting iteration, the last one being
+    /// [`MultiOutputForest::predict`].
+    pub fn staged_predict<'a>(&'a self, x: &'a [F]) -> impl Iterator<Item = Vec<NotNan<F>>> + 'a {
+        let trees_per_iteration = self.trees_per_iteration();
+        let mut stages: Vec<_> = self
+            .forests
+            .iter()
+            .map(|forest| forest.staged_predict_iterations(x, trees_per_iteration))
+            .collect();
+        std::iter::from_fn(move || stages.iter_mut().map(Iterator::next).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::parser::{read_lightgbm_model, read_xgboost_model, test_utils::read_features};
+
+    #[test]
+    fn test_iteration_range() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/xgboost/multiclass_classification");
+        let mut model = read_xgboost_model(data_dir.join("model.json")).unwrap();
+        let n_iterations = model.n_iterations();
+        assert_eq!(n_iterations, model.forests[0].trees.len());
+
+        for x in read_features(&data_dir.join("X.csv")).iter().take(10) {
+            let stages: Vec<_> = model.staged_predict(x).collect();
+            assert_eq!(stages.len(), n_iterations);
+            assert_eq!(stages.last().unwrap(), &model.predict(x));
+            for (i, stage) in stages.iter().enumerate() {
+                assert_eq!(stage, &model.predict_iteration_range(x, 0..i + 1));
+            }
+
+            let base_values: Vec<_> = model.forests.iter().map(|f| f.base_value).collect();
+            for range in [0..0, 3..3, n_iterations + 1..n_iterations + 5] {
+                let predictions = model.predict_iteration_range(x, range);
+                assert!(
+                    predictions
+                        .iter()
+                        .map(|p| p.into_inner())
+                        .eq(base_values.clone())
+                );
+            }
+            assert_eq!(
+                model.predict_iteration_range(x, 0..n_iterations + 10),
+                model.predict(x)
+            );
+            let forest = &model.forests[1];
+            let middle = forest.predict_iteration_range(x, 2..5).into_inner();
+            let expected = forest.base_value
+                + (2..5)
+                    .map(|i| forest.trees[i].predict(x).into_inner())
+                    .sum::<f64>();
+            assert!((middle - expected).abs() < 1e-12);
+            assert_eq!(forest.staged_predict(x).nth(3).unwrap(), stages[3][1]);
+
+            assert_eq!(model.predict_best_iteration(x), model.predict(x));
+            model.metadata_mut().best_iteration = Some(3);
+            assert_eq!(model.predict_best_iteration(x), stages[3]);
+            model.metadata_mut().best_iteration = None;
+        }
+    }
+
+    #[test]
+    fn test_trees_per_iteration() {
+        // two trees per iteration, as XGBoost random forests with `num_parallel_tree = 2`
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/lightgbm/regression");
+        let mut model = read_lightgbm_model(data_dir.join("model.txt")).unwrap();
+        let n_trees = model.forests[0].trees.len();
+        model.metadata_mut().trees_per_iteration = Some(2);
+        assert_eq!(model.n_iterations(), n_trees.div_ceil(2));
+
+        let x = &read_features(&data_dir.join("X.csv"))[0];
+        let forest = &model.forests[0];
+        assert_eq!(
+            model.predict_iteration_range(x, 1..2)[0],
+            forest.predict_iteration_range(x, 2..4)
+        );
+        let stages: Vec<_> = model.staged_predict(x).collect();
+        assert_eq!(stages.len(), model.n_iterations());
+        assert_eq!(stages[0][0], forest.predict_iteration_range(x, 0..2));
+        assert_eq!(stages.last().unwrap(), &model.predict(x));
+    }
+}
@@ -1,8 +1,36 @@
+#[cfg(feature = "arrow")]
+pub mod arrow;
+mod decision;
+mod error;
+mod float;
 mod forest;
+mod iteration;
 mod map;
+mod metadata;
+#[cfg(feature = "nalgebra")]
+mod nalgebra;
+mod native;
+#[cfg(feature = "ndarray")]
+mod ndarray;
+#[cfg(feature = "polars")]
+pub mod polars;
 mod tree;
 
+pub mod binary;
+pub mod codegen;
+pub mod explain;
+pub mod features;
+pub mod importance;
+pub mod monotonicity;
 pub mod parser;
+pub mod quantize;
+pub mod range;
+pub mod sparse;
 
+pub use decision::Decision;
+pub use error::Error;
+pub use float::Float;
 pub use forest::{Forest, MultiOutputForest};
-pub use tree::{Tree, TreeNode};
+pub use metadata::{ModelMetadata, ModelSource};
+pub use native::NATIVE_FORMAT_VERSION;
+pub use tree::{LeafNumbering, Tree, TreeNode};
@@ -0,0 +1,71 @@
+use std::{
+    collections::BTreeMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Descriptive information about a model, carried alongside its trees.
+///
+/// Every field is optional; parsers fill in what the source format provides.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelMetadata {
+    /// Feature names, indexed like the input rows.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub feature_names: Vec<String>,
+    /// Feature types as named by the source library, e.g. `float`, `int` or `c` (categorical).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub feature_types: Vec<String>,
+    /// Number of input features the model was trained on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n_features: Option<usize>,
+    /// Training objective, which determines the transform of the raw outputs, e.g.
+    /// `binary:logistic` or `multiclass`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub objective: Option<String>,
+    /// Library the model was converted from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<ModelSource>,
+    /// Trees added per output in every boosting iteration, e.g. XGBoost's `num_parallel_tree`;
+    /// one when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trees_per_iteration: Option<usize>,
+    /// 0-based index of the best iteration found by early stopping, as XGBoost counts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub best_iteration: Option<usize>,
+    /// Creation time of the Silva model, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<u64>,
+    /// Free-form key-values, including attributes stored by the source library.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelSource {
+    /// `xgboost` or `lightgbm`.
+    pub library: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+impl ModelMetadata {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Index of the feature called `name`.
+    pub fn feature_index(&self, name: &str) -> Option<usize> {
+        self.feature_names.iter().position(|n| n == name)
+    }
+
+    /// Set `created_at` to the current time. Parsers leave it unset, so that converting the same
+    /// file twice gives identical models.
+    pub fn created_now(mut self) -> Self {
+        self.created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_secs());
+        self
+    }
+}
@@ -0,0 +1,501 @@
+//! Verification of monotone constraints, proving that a forest respects them or finding inputs
+//! that break them.
+//!
+//! Along a constrained feature, with every other feature fixed, a forest is piecewise constant
+//! with steps at the thresholds of its splits on that feature, so it is monotone when no step
+//! goes the wrong way. The trees whose prediction changes at a step can be bounded jointly over
+//! boxes of the other features; boxes where the bound allows a violation are split on the
+//! thresholds of these trees until the bound rules it out or a concrete input violates it.
+
+use ordered_float::NotNan;
+
+use crate::{
+    Error, Float, Forest, Tree, TreeNode,
+    error::malformed_tree,
+    range::{FeatureDomain, Interval},
+};
+
+/// Boxes [`Forest::verify_monotonicity`] may bound before giving up.
+pub const DEFAULT_MAX_BOXES: usize = 100_000;
+
+/// Required direction of the prediction as a feature increases.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Monotone {
+    Increasing,
+    Decreasing,
+    #[default]
+    Unconstrained,
+}
+
+impl From<i8> for Monotone {
+    /// The sign convention of XGBoost's and LightGBM's `monotone_constraints`: positive for
+    /// increasing, negative for decreasing and zero for unconstrained.
+    fn from(constraint: i8) -> Self {
+        match constraint.signum() {
+            1 => Monotone::Increasing,
+            -1 => Monotone::Decreasing,
+            _ => Monotone::Unconstrained,
+        }
+    }
+}
+
+/// Two inputs differing in a single feature whose predictions go against its constraint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonotonicityViolation<F: Float = f64> {
+    /// The feature whose constraint is violated.
+    pub feature: usize,
+    /// Input with the smaller value of `feature`.
+    pub lower: Vec<F>,
+    /// Input with the larger value of `feature`, equal to `lower` elsewhere.
+    pub upper: Vec<F>,
+    pub lower_prediction: F,
+    pub upper_prediction: F,
+}
+
+fn node<F: Float>(tree: &Tree<F>, id: usize) -> Result<&TreeNode<F>, Error> {
+    tree.node_map
+        .get(&id)
+        .ok_or_else(|| malformed_tree(format!("node {id} does not exist")))
+}
+
+/// Largest drop of a tree's prediction from the input with the constrained feature at `from` to
+/// the one with it at `to`, over the pairs of inputs of a box equal in every other feature.
+struct StepBound<'a, F: Float> {
+    tree: &'a Tree<F>,
+    feature: usize,
+    from: F,
+    to: F,
+    /// Other features of the box, shared by both inputs.
+    intervals: Vec<Interval<F>>,
+    max: Option<f64>,
+    /// A split of another feature cutting the box, to refine it on.
+    cut: Option<(usize, F)>,
+}
+
+impl<F: Float> StepBound<'_, F> {
+    /// Follow the `from` input down from `from_node` and the `to` input down from `to_node`, one
+    /// node at a time, branching wherever a split on another feature cuts the box.
+    fn visit(&mut self, from_node: usize, to_node: usize, depth: usize) -> Result<(), Error> {
+        // each input visits every node at most once on a valid path
+        if depth >= 2 * self.tree.node_map.len() {
+            return Err(malformed_tree("cycle in tree"));
+        }
+        let (from_node, to_node) = (node(self.tree, from_node)?, node(self.tree, to_node)?);
+        let advance_from = !from_node.is_leaf();
+        let (current, value) = match (advance_from, to_node.is_leaf()) {
+            (false, true) => {
+                let drop: f64 = (from_node.value - to_node.value).into_inner().into();
+                self.max = Some(self.max.map_or(drop, |max| max.max(drop)));
+                return Ok(());
+            }
+            (true, _) => (from_node, self.from),
+            (false, false) => (to_node, self.to),
+        };
+        let next = |child: usize| {
+            if advance_from {
+                (child, to_node.id)
+            } else {
+                (from_node.id, child)
+            }
+        };
+        let (Some(left), Some(right)) = (current.left, current.right) else {
+            return Err(malformed_tree(format!(
+                "node {} has a single child",
+                current.id
+            )));
+        };
+
+        let threshold = current.split_condition.into_inner();
+        if current.split_index == self.feature {
+            let (from, to) = next(if value < threshold { left } else { right });
+            return self.visit(from, to, depth + 1);
+        }
+        let feature = current.split_index;
+        let interval = self.intervals[feature];
+        let children = [
+            (left, interval.left(threshold, None)),
+            (right, interval.right(threshold, None)),
+        ];
+        if children
+            .iter()
+            .all(|(_, restricted)| !restricted.is_empty())
+        {
+            self.cut.get_or_insert((feature, threshold));
+        }
+        for (child, restricted) in children {
+            if !restricted.is_empty() {
+                self.intervals[feature] = restricted;
+                let (from, to) = next(child);
+                self.visit(from, to, depth + 1)?;
+            }
+        }
+        self.intervals[feature] = interval;
+        Ok(())
+    }
+}
+
+/// Number of boxes bounded so far, out of the allowed `max_boxes`.
+struct Budget {
+    max_boxes: usize,
+    used: usize,
+}
+
+impl Budget {
+    fn spend(&mut self) -> Result<(), Error> {
+        if self.used == self.max_boxes {
+            return Err(Error::Inconclusive {
+                max_boxes: self.max_boxes,
+            });
+        }
+        self.used += 1;
+        Ok(())
+    }
+}
+
+/// A value within `interval`, the lower bound when it is finite.
+fn representative<F: Float>(interval: &Interval<F>) -> F {
+    if interval.lo.is_finite() {
+        interval.lo
+    } else if interval.hi.is_finite() {
+        below(interval.hi)
+    } else {
+        F::from_f64(0.0)
+    }
+}
+
+/// A value strictly below `value`.
+fn below<F: Float>(value: F) -> F {
+    value - value.abs().max(F::from_f64(1.0))
+}
+
+impl<F: Float> Forest<F> {
+    /// Check that the prediction moves in the direction `constraints` requires for each
+    /// feature, features past the end of the slice being unconstrained (see
+    /// [`Monotone::from`] for XGBoost's and LightGBM's notation).
+    ///
+    /// Returns `Ok(None)` when the forest is proven monotone over all non-missing inputs, and
+    /// otherwise a pair of inputs violating a constraint, confirmed with
+    /// [`Forest::try_predict`]. The proof assumes exact summation of the trees: a step whose
+    /// leaf values cancel exactly may still be off by a rounding error.
+    ///
+    /// Refining boxes can take exponential time on forests with many trees taking the same
+    /// step, so this gives up with [`Error::Inconclusive`] after [`DEFAULT_MAX_BOXES`] boxes.
+    pub fn verify_monotonicity(
+        &self,
+        constraints: &[Monotone],
+    ) -> Result<Option<MonotonicityViolation<F>>, Error> {
+        self.verify_monotonicity_with_budget(constraints, DEFAULT_MAX_BOXES)
+    }
+
+    /// [`Self::verify_monotonicity`] bounding at most `max_boxes` boxes over all features.
+    pub fn verify_monotonicity_with_budget(
+        &self,
+        constraints: &[Monotone],
+        max_boxes: usize,
+    ) -> Result<Option<MonotonicityViolation<F>>, Error> {
+        let mut budget = Budget { max_boxes, used: 0 };
+        for (feature, &constraint) in constraints.iter().enumerate() {
+            let violation = match constraint {
+                Monotone::Increasing => self.find_violation(feature, false, &mut budget)?,
+                Monotone::Decreasing => self.find_violation(feature, true, &mut budget)?,
+                Monotone::Unconstrained => None,
+            };
+            if violation.is_some() {
+                return Ok(violation);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Inputs whose prediction decreases with `feature`, or increases when `decreasing`.
+    fn find_violation(
+        &self,
+        feature: usize,
+        decreasing: bool,
+        budget: &mut Budget,
+    ) -> Result<Option<MonotonicityViolation<F>>, Error> {
+        let mut thresholds: Vec<NotNan<F>> = self
+            .trees
+            .iter()
+            .flat_map(|tree| tree.node_map.values())
+            .filter(|node| !node.is_leaf() && node.split_index == feature)
+            .map(|node| node.split_condition)
+            .collect();
+        thresholds.sort();
+        thresholds.dedup();
+        let thresholds: Vec<F> = thresholds.into_iter().map(NotNan::into_inner).collect();
+
+        let n_features = self.n_features().max(feature + 1);
+        let unbounded = Interval::new(&FeatureDomain::new(F::neg_infinity(), F::infinity()));
+        for (i, &threshold) in thresholds.iter().enumerate() {
+            // the step at `threshold`, from the start of the previous interval to its own
+            let below = if i == 0 {
+                below(threshold)
+            } else {
+                thresholds[i - 1]
+            };
+            // the prediction must not drop from `from` to `to`
+            let (from, to) = if decreasing {
+                (threshold, below)
+            } else {
+                (below, threshold)
+            };
+            // only trees splitting on the feature at this threshold take the step
+            let stepping: Vec<&Tree<F>> = self
+                .trees
+                .iter()
+                .filter(|tree| {
+                    tree.node_map.values().any(|node| {
+                        !node.is_leaf()
+                            && node.split_index == feature
+                            && node.split_condition.into_inner() == threshold
+                    })
+                })
+                .collect();
+
+            let mut boxes = vec![vec![unbounded; n_features]];
+            while let Some(intervals) = boxes.pop() {
+                budget.spend()?;
+                let mut bound = 0.0;
+                let mut cut = None;
+                for tree in &stepping {
+                    let mut step = StepBound {
+                        tree,
+                        feature,
+                        from,
+                        to,
+                        intervals: intervals.clone(),
+                        max: None,
+                        cut: None,
+                    };
+                    step.visit(tree.root, tree.root, 0)?;
+                    bound += step
+                        .max
+                        .ok_or_else(|| malformed_tree("no leaf is reachable"))?;
+                    cut = cut.or(step.cut);
+                }
+                if bound <= 0.0 {
+                    continue;
+                }
+
+                let mut from_x: Vec<F> = intervals.iter().map(representative).collect();
+                let mut to_x = from_x.clone();
+                from_x[feature] = from;
+                to_x[feature] = to;
+                let from_prediction = self.try_predict(&from_x)?.into_inner();
+                let to_prediction = self.try_predict(&to_x)?.into_inner();
+                if from_prediction > to_prediction {
+                    let (lower, upper, lower_prediction, upper_prediction) = if decreasing {
+                        (to_x, from_x, to_prediction, from_prediction)
+                    } else {
+                        (from_x, to_x, from_prediction, to_prediction)
+                    };
+                    return Ok(Some(MonotonicityViolation {
+                        feature,
+                        lower,
+                        upper,
+                        lower_prediction,
+                        upper_prediction,
+                    }));
+                }
+                // without a cut, the box is a single cell and its bound is exact
+                if let Some((split_feature, split_threshold)) = cut {
+                    let interval = intervals[split_feature];
+                    for restricted in [
+                        interval.left(split_threshold, None),
+                        interval.right(split_threshold, None),
+                    ] {
+                        let mut refined = intervals.clone();
+                        refined[split_feature] = restricted;
+                        boxes.push(refined);
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::parser::{read_lightgbm_model, read_xgboost_model, test_utils::read_features};
+
+    /// An increasing step of 1 at x0 = 5, plus a decreasing step of `drop` at x0 = 5 when
+    /// x1 < 0.
+    fn forest(drop: f64) -> Forest {
+        let increasing = Tree::from_nodes(vec![
+            TreeNode::split(0, 0, 5.0, 1, 2),
+            TreeNode::leaf(1, 1.0),
+            TreeNode::leaf(2, 2.0),
+        ]);
+        let decreasing = Tree::from_nodes(vec![
+            TreeNode::split(0, 1, 0.0, 1, 2),
+            TreeNode::split(1, 0, 5.0, 3, 4),
+            TreeNode::leaf(2, 0.0),
+            TreeNode::leaf(3, drop),
+            TreeNode::leaf(4, 0.0),
+        ]);
+        Forest::new(0.5, vec![increasing, decreasing])
+    }
+
+    fn check_violation(forest: &Forest, violation: &MonotonicityViolation, constraint: Monotone) {
+        let feature = violation.feature;
+        assert!(violation.lower[feature] < violation.upper[feature]);
+        for (i, (lower, upper)) in violation.lower.iter().zip(&violation.upper).enumerate() {
+            assert!(i == feature || lower == upper);
+        }
+        assert_eq!(
+            forest.predict(&violation.lower).into_inner(),
+            violation.lower_prediction
+        );
+        assert_eq!(
+            forest.predict(&violation.upper).into_inner(),
+            violation.upper_prediction
+        );
+        match constraint {
+            Monotone::Increasing => {
+                assert!(violation.lower_prediction > violation.upper_prediction)
+            }
+            Monotone::Decreasing => {
+                assert!(violation.lower_prediction < violation.upper_prediction)
+            }
+            Monotone::Unconstrained => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_verify_monotonicity() {
+        use Monotone::*;
+
+        // the second tree alone decreases, but never by more than the first increases
+        let monotone = forest(0.5);
+        assert_eq!(monotone.verify_monotonicity(&[Increasing]).unwrap(), None);
+        assert_eq!(monotone.verify_monotonicity(&[]).unwrap(), None);
+        assert_eq!(
+            monotone
+                .verify_monotonicity(&[Unconstrained, Decreasing])
+                .unwrap(),
+            None
+        );
+        let violation = monotone
+            .verify_monotonicity(&[Decreasing])
+            .unwrap()
+            .unwrap();
+        check_violation(&monotone, &violation, Decreasing);
+        let violation = monotone
+            .verify_monotonicity(&[Unconstrained, Increasing])
+            .unwrap()
+            .unwrap();
+        assert_eq!(violation.feature, 1);
+        check_violation(&monotone, &violation, Increasing);
+
+        // with x1 < 0, the prediction drops from 3.5 to 2.5 at x0 = 5
+        let violating = forest(2.0);
+        let violation = violating
+            .verify_monotonicity(&[Increasing])
+            .unwrap()
+            .unwrap();
+        check_violation(&violating, &violation, Increasing);
+        assert!(violation.lower[1] < 0.0);
+        assert_eq!(
+            (violation.lower_prediction, violation.upper_prediction),
+            (3.5, 2.5)
+        );
+
+        // a third tree making up for the drop, which only bounding x1 < 0 apart reveals
+        let mut refined = forest(1.5);
+        refined.trees.push(Tree::from_nodes(vec![
+            TreeNode::split(0, 1, 0.0, 1, 2),
+            TreeNode::split(1, 0, 5.0, 3, 4),
+            TreeNode::leaf(2, 0.0),
+            TreeNode::leaf(3, 0.0),
+            TreeNode::leaf(4, 1.0),
+        ]));
+        assert_eq!(refined.verify_monotonicity(&[Increasing]).unwrap(), None);
+        // the proof needs the root box and its two halves
+        assert!(matches!(
+            refined.verify_monotonicity_with_budget(&[Increasing], 2),
+            Err(Error::Inconclusive { max_boxes: 2 })
+        ));
+        assert_eq!(
+            refined
+                .verify_monotonicity_with_budget(&[Increasing], 3)
+                .unwrap(),
+            None
+        );
+        refined.trees[2].node_map.get_mut(&4).unwrap().value = NotNan::new(0.25).unwrap();
+        let violation = refined.verify_monotonicity(&[Increasing]).unwrap().unwrap();
+        check_violation(&refined, &violation, Increasing);
+    }
+
+    #[test]
+    fn test_from_sign() {
+        assert_eq!(Monotone::from(1), Monotone::Increasing);
+        assert_eq!(Monotone::from(-1), Monotone::Decreasing);
+        assert_eq!(Monotone::from(0), Monotone::Unconstrained);
+    }
+
+    /// Verify every feature in both directions, checking proofs along every row.
+    fn check_forest(forest: &Forest, rows: &[Vec<f64>]) {
+        for feature in 0..rows[0].len() {
+            let mut thresholds: Vec<f64> = forest
+                .trees
+                .iter()
+                .flat_map(|tree| tree.node_map.values())
+                .filter(|node| !node.is_leaf() && node.split_index == feature)
+                .map(|node| node.split_condition.into_inner())
+                .collect();
+            thresholds.sort_by(f64::total_cmp);
+            for constraint in [Monotone::Increasing, Monotone::Decreasing] {
+                let mut constraints = vec![Monotone::Unconstrained; feature + 1];
+                constraints[feature] = constraint;
+                match forest.verify_monotonicity(&constraints).unwrap() {
+                    Some(violation) => {
+                        assert_eq!(violation.feature, feature);
+                        check_violation(forest, &violation, constraint);
+                    }
+                    None => {
+                        for x in rows.iter().filter(|x| x.iter().all(|v| !v.is_nan())) {
+                            let mut x = x.clone();
+                            let predictions: Vec<f64> = thresholds
+                                .iter()
+                                .map(|&threshold| {
+                                    x[feature] = threshold;
+                                    forest.predict(&x).into_inner()
+                                })
+                                .collect();
+                            for pair in predictions.windows(2) {
+                                let tolerance = 1e-9 * pair[0].abs().max(1.0);
+                                match constraint {
+                                    Monotone::Increasing => {
+                                        assert!(pair[0] <= pair[1] + tolerance)
+                                    }
+                                    _ => assert!(pair[0] + tolerance >= pair[1]),
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify_monotonicity_xgboost() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/xgboost/regression");
+        let model = read_xgboost_model(data_dir.join("model.json")).unwrap();
+        check_forest(&model.forests[0], &read_features(&data_dir.join("X.csv")));
+    }
+
+    #[test]
+    fn test_verify_monotonicity_lightgbm() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/lightgbm/regression");
+        let model = read_lightgbm_model(data_dir.join("model.txt")).unwrap();
+        check_forest(&model.forests[0], &read_features(&data_dir.join("X.csv")));
+    }
+}
@@ -0,0 +1,48 @@
+//! Batch prediction on `nalgebra` matrices, enabled by the `nalgebra` feature.
+
+use nalgebra::DMatrix;
+
+use crate::{Float, MultiOutputForest};
+
+impl<F: Float> MultiOutputForest<F> {
+    /// Predict every row of `x`, returning one column per output.
+    pub fn predict_dmatrix(&self, x: &DMatrix<F>) -> DMatrix<F> {
+        let mut outputs = DMatrix::from_element(x.nrows(), self.forests.len(), F::zero());
+        // matrices are column-major, so each row is gathered into a contiguous buffer
+        let mut row = Vec::with_capacity(x.ncols());
+        for i in 0..x.nrows() {
+            row.clear();
+            row.extend(x.row(i).iter().copied());
+            for (j, prediction) in self.predict(&row).into_iter().enumerate() {
+                outputs[(i, j)] = prediction.into_inner();
+            }
+        }
+        outputs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use nalgebra::DMatrix;
+
+    use crate::parser::{read_lightgbm_model, test_utils::read_features};
+
+    #[test]
+    fn test_predict_dmatrix() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/lightgbm/multiclass_classification");
+        let model = read_lightgbm_model(data_dir.join("model.txt")).unwrap();
+        let rows = read_features(&data_dir.join("X.csv"));
+
+        let x = DMatrix::from_fn(rows.len(), rows[0].len(), |i, j| rows[i][j]);
+        let outputs = model.predict_dmatrix(&x);
+        assert_eq!(outputs.shape(), (rows.len(), 3));
+        for (i, x) in rows.iter().enumerate() {
+            for (j, expected) in model.predict(x).into_iter().enumerate() {
+                assert_eq!(outputs[(i, j)], expected.into_inner());
+            }
+        }
+    }
+}
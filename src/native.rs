@@ -0,0 +1,230 @@
+//! Versioning of the native JSON format of [`MultiOutputForest`].
+//!
+//! Files carry a top-level `version`, written before the forests so that readers can reject a
+//! newer format before reading anything else. Version 1 is the original layout without the tag,
+//! and version 2 added it.
+//!
+//! Reading is backward compatible rather than migrating: version 2 only added the tag, so a
+//! version 1 file is read as it is, with no conversion step, and gives the same model as the
+//! version 2 file written from it. A bump that changes the meaning of existing fields must add
+//! an explicit conversion of older files instead.
+//!
+//! [`QuantizedForest`](crate::quantize::QuantizedForest) files carry the same `version` through
+//! [`FormatVersion`], with version 1 their layout before the tag.
+//!
+//! Adding an optional field with a default, like the `dl`, `c`, `iv`, `g` and `h` node fields
+//! and `metadata`, does not change the version: older readers ignore the field and newer readers
+//! fill in the default. The version is bumped, with a frozen file added to the corpus of the
+//! tests, only for changes that older files cannot be read through that way.
+
+use std::{fmt, marker::PhantomData};
+
+use serde::{
+    Deserialize, Deserializer, Serialize, Serializer,
+    de::{self, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    ser::SerializeStruct,
+};
+
+use crate::{Float, ModelMetadata, MultiOutputForest};
+
+/// Version of the native format written by this crate.
+pub const NATIVE_FORMAT_VERSION: u32 = 2;
+
+const FIELDS: &[&str] = &["version", "forests", "metadata"];
+
+fn check_version<E: de::Error>(version: u32) -> Result<(), E> {
+    if version == 0 || version > NATIVE_FORMAT_VERSION {
+        return Err(E::custom(format!(
+            "unsupported native format version {version}, expected at most {NATIVE_FORMAT_VERSION}"
+        )));
+    }
+    Ok(())
+}
+
+/// `version` field of the other native files, written as [`NATIVE_FORMAT_VERSION`] and checked
+/// like the one of [`MultiOutputForest`] when read. Declare it first, with `#[serde(default)]`
+/// for files written before the tag.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct FormatVersion;
+
+impl Serialize for FormatVersion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        NATIVE_FORMAT_VERSION.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for FormatVersion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        check_version(u32::deserialize(deserializer)?)?;
+        Ok(FormatVersion)
+    }
+}
+
+impl<F: Float> Serialize for MultiOutputForest<F> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // formats that are not self-describing need every field, in order
+        let skip_metadata = serializer.is_human_readable() && self.metadata.is_empty();
+        let mut state =
+            serializer.serialize_struct("MultiOutputForest", if skip_metadata { 2 } else { 3 })?;
+        state.serialize_field("version", &NATIVE_FORMAT_VERSION)?;
+        state.serialize_field("forests", &self.forests)?;
+        if skip_metadata {
+            state.skip_field("metadata")?;
+        } else {
+            state.serialize_field("metadata", &self.metadata)?;
+        }
+        state.end()
+    }
+}
+
+struct ModelVisitor<F>(PhantomData<F>);
+
+impl<'de, F: Float> Visitor<'de> for ModelVisitor<F> {
+    type Value = MultiOutputForest<F>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a native Silva model")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut forests = None;
+        let mut metadata = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "version" => check_version(map.next_value()?)?,
+                "forests" => forests = Some(map.next_value()?),
+                "metadata" => metadata = Some(map.next_value()?),
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        let forests = forests.ok_or_else(|| de::Error::missing_field("forests"))?;
+        Ok(MultiOutputForest::new(forests).with_metadata(metadata.unwrap_or_default()))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let version = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        check_version(version)?;
+        let forests = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let metadata: Option<ModelMetadata> = seq.next_element()?;
+        Ok(MultiOutputForest::new(forests).with_metadata(metadata.unwrap_or_default()))
+    }
+}
+
+impl<'de, F: Float> Deserialize<'de> for MultiOutputForest<F> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_struct("MultiOutputForest", FIELDS, ModelVisitor(PhantomData))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use serde_json::Value;
+
+    use super::*;
+    use crate::parser::test_utils::{read_features, test_model_prediction};
+
+    /// Frozen native files, their format version and the tolerance of their expected outputs.
+    /// They must keep loading and predicting forever; add a file here for every new version
+    /// instead of regenerating old ones (see the module documentation for what changes it).
+    const CORPUS: [(&str, u32, f64); 2] = [
+        // expected outputs computed by XGBoost in float32
+        ("multiclass_classification", 1, 0.05),
+        // expected outputs computed by XGBoost in float32, with missing values
+        ("binary_classification_v2", 2, 1e-5),
+    ];
+
+    #[test]
+    fn test_frozen_corpus() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_data/native");
+        for (name, version, tolerance) in CORPUS {
+            let data_dir = root.join(name);
+            let content = std::fs::read_to_string(data_dir.join("model.json")).unwrap();
+            let value: Value = serde_json::from_str(&content).unwrap();
+            let stored = value.get("version").and_then(Value::as_u64).unwrap_or(1);
+            assert_eq!(stored, version as u64, "{name}");
+
+            let model: MultiOutputForest = serde_json::from_str(&content).unwrap();
+            test_model_prediction(&data_dir, &model, tolerance)
+                .unwrap_or_else(|e| panic!("frozen model {name} failed: {e}"));
+
+            let written = serde_json::to_value(&model).unwrap();
+            assert_eq!(written["version"], NATIVE_FORMAT_VERSION);
+        }
+    }
+
+    #[test]
+    fn test_backward_compatible_read() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_data/native");
+
+        // a version 1 file and the version 2 file written from it
+        let data_dir = root.join("multiclass_classification");
+        let content = std::fs::read_to_string(data_dir.join("model.json")).unwrap();
+        let v1_value: Value = serde_json::from_str(&content).unwrap();
+        let v1: MultiOutputForest = serde_json::from_value(v1_value.clone()).unwrap();
+        let v2_value = serde_json::to_value(&v1).unwrap();
+        assert_eq!(v2_value["version"], 2);
+        let v2: MultiOutputForest = serde_json::from_value(v2_value.clone()).unwrap();
+
+        // same fields, with only the tag added, and the same metadata and predictions
+        let mut tagged = v1_value;
+        tagged["version"] = 2.into();
+        assert_eq!(v2_value, tagged);
+        assert_eq!(v1.metadata(), v2.metadata());
+        for x in read_features(&data_dir.join("X.csv")) {
+            assert_eq!(v1.predict(&x), v2.predict(&x));
+        }
+
+        // a version 2 file without the tag is read as version 1, to the same model
+        let data_dir = root.join("binary_classification_v2");
+        let content = std::fs::read_to_string(data_dir.join("model.json")).unwrap();
+        let v2_value: Value = serde_json::from_str(&content).unwrap();
+        let v2: MultiOutputForest = serde_json::from_value(v2_value.clone()).unwrap();
+        let mut untagged = v2_value.clone();
+        untagged.as_object_mut().unwrap().remove("version");
+        let v1: MultiOutputForest = serde_json::from_value(untagged).unwrap();
+        assert_eq!(serde_json::to_value(&v1).unwrap(), v2_value);
+        assert_eq!(v1.metadata(), v2.metadata());
+        for x in read_features(&data_dir.join("X.csv")) {
+            assert_eq!(v1.predict(&x), v2.predict(&x));
+        }
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let content = format!(
+            r#"{{"version": {}, "forests": []}}"#,
+            NATIVE_FORMAT_VERSION + 1
+        );
+        let err = serde_json::from_str::<MultiOutputForest>(&content).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("unsupported native format version")
+        );
+
+        // the version is checked before the forests are read
+        let content = format!(
+            r#"{{"version": {}, "forests": "changed"}}"#,
+            NATIVE_FORMAT_VERSION + 1
+        );
+        let err = serde_json::from_str::<MultiOutputForest>(&content).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("unsupported native format version")
+        );
+
+        let model: MultiOutputForest = serde_json::from_str(r#"{"forests": []}"#).unwrap();
+        assert!(model.forests.is_empty());
+        let model: MultiOutputForest =
+            serde_json::from_str(r#"{"forests": [], "future": {"optional": 1}}"#).unwrap();
+        assert!(model.forests.is_empty());
+        assert!(serde_json::from_str::<MultiOutputForest>(r#"{"version": 2}"#).is_err());
+    }
+}
@@ -0,0 +1,69 @@
+//! Batch prediction on `ndarray` arrays, enabled by the `ndarray` feature.
+
+use ndarray::{Array2, ArrayView2};
+
+use crate::{Float, MultiOutputForest};
+
+impl<F: Float> MultiOutputForest<F> {
+    /// Predict every row of `x`, in any memory order, returning one column per output.
+    pub fn predict_array(&self, x: ArrayView2<F>) -> Array2<F> {
+        let mut outputs = Array2::zeros((x.nrows(), self.forests.len()));
+        let mut buffer = Vec::with_capacity(x.ncols());
+        for (row, mut output) in x.rows().into_iter().zip(outputs.rows_mut()) {
+            // rows of column-major or strided arrays are copied into a contiguous buffer
+            let row = match row.as_slice() {
+                Some(row) => row,
+                None => {
+                    buffer.clear();
+                    buffer.extend(row.iter().copied());
+                    &buffer
+                }
+            };
+            for (value, prediction) in output.iter_mut().zip(self.predict(row)) {
+                *value = prediction.into_inner();
+            }
+        }
+        outputs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use ndarray::{Array2, ShapeBuilder};
+
+    use crate::parser::{read_xgboost_model, test_utils::read_features};
+
+    #[test]
+    fn test_predict_array() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/xgboost/multiclass_classification");
+        let model = read_xgboost_model(data_dir.join("model.json")).unwrap();
+        let rows = read_features(&data_dir.join("X.csv"));
+        let shape = (rows.len(), rows[0].len());
+
+        let row_major = Array2::from_shape_fn(shape, |(i, j)| rows[i][j]);
+        let column_major = Array2::from_shape_fn(shape.f(), |(i, j)| rows[i][j]);
+        let expected = model.predict_array(row_major.view());
+        assert_eq!(expected.dim(), (rows.len(), 3));
+        assert_eq!(model.predict_array(column_major.view()), expected);
+        for (x, output) in rows.iter().zip(expected.rows()) {
+            let x: Vec<f64> = model.predict(x).iter().map(|v| v.into_inner()).collect();
+            assert_eq!(output.to_vec(), x);
+        }
+
+        let model_f32 = model.cast::<f32>();
+        let x_f32 = row_major.mapv(|v| v as f32);
+        let outputs = model_f32.predict_array(x_f32.view());
+        assert_eq!(outputs.dim(), expected.dim());
+        for (x, output) in x_f32.rows().into_iter().zip(outputs.rows()) {
+            let x: Vec<f32> = model_f32
+                .predict(&x.to_vec())
+                .iter()
+                .map(|v| v.into_inner())
+                .collect();
+            assert_eq!(output.to_vec(), x);
+        }
+    }
+}
@@ -4,7 +4,7 @@ pub mod xgboost;
 pub use xgboost::read_xgboost_model;
 
 mod lightgbm;
-pub use lightgbm::read_lightgbm_model;
+pub use lightgbm::{LightGBMError, read_lightgbm_model};
 
 #[cfg(test)]
-mod test_utils;
+pub(crate) mod test_utils;
@@ -2,16 +2,55 @@ use std::path::Path;
 
 use serdeio::read_record_from_file;
 
-use crate::{Forest, MultiOutputForest};
+use crate::{
+    Float, Forest, MultiOutputForest,
+    quantize::{BinIndex, QuantizedForest},
+};
 
-impl Forest {
+impl<F: Float> Forest<F> {
     pub fn from_file(path: impl AsRef<Path>) -> Result<Self, serdeio::Error> {
         read_record_from_file(path)
     }
 }
 
-impl MultiOutputForest {
+impl<F: Float> MultiOutputForest<F> {
     pub fn from_file(path: impl AsRef<Path>) -> Result<Self, serdeio::Error> {
         read_record_from_file(path)
     }
 }
+
+impl<B: BinIndex, F: Float> QuantizedForest<B, F> {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, serdeio::Error> {
+        read_record_from_file(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{MultiOutputForest, parser::read_lightgbm_model};
+
+    #[test]
+    fn test_metadata_round_trip() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let mut model =
+            read_lightgbm_model(root.join("test_data/lightgbm/binary_classification/model.txt"))
+                .unwrap();
+        model
+            .metadata_mut()
+            .attributes
+            .insert("owner".to_string(), "risk".to_string());
+
+        let path = std::env::temp_dir().join(format!("silva-metadata-{}.json", std::process::id()));
+        serdeio::write_record_to_file(&path, &model).unwrap();
+        let loaded = MultiOutputForest::<f64>::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.metadata(), model.metadata());
+
+        // models written without metadata still load
+        let native = root.join("test_data/native/multiclass_classification/model.json");
+        let model = MultiOutputForest::<f64>::from_file(native).unwrap();
+        assert!(model.metadata().is_empty());
+    }
+}
@@ -1,11 +1,12 @@
 use std::path::Path;
 
 use itertools::izip;
+use ordered_float::NotNan;
 use std::result::Result;
 use thiserror::Error;
 
 use crate::{
-    Forest, MultiOutputForest,
+    Forest, ModelMetadata, ModelSource, MultiOutputForest,
     tree::{Tree, TreeNode},
 };
 
@@ -27,7 +28,7 @@ pub enum LightGBMError {
 }
 
 pub fn read_lightgbm_model(path: impl AsRef<Path>) -> Result<MultiOutputForest, LightGBMError> {
-    let tree_records = read_lightgbm_txt(path)?;
+    let (tree_records, metadata) = read_lightgbm_txt(path)?;
     let trees = tree_records
         .into_iter()
         .map(|records| records.into_iter().map(Tree::from).collect::<Vec<Tree>>())
@@ -39,29 +40,35 @@ pub fn read_lightgbm_model(path: impl AsRef<Path>) -> Result<MultiOutputForest,
         .into_iter()
         .map(|tree_vec| Forest::new(0.0, tree_vec))
         .collect::<Vec<Forest>>();
-    Ok(MultiOutputForest::new(forests))
+    Ok(MultiOutputForest::new(forests).with_metadata(metadata))
 }
 
 #[derive(Clone)]
 struct LGBMTreeRecord {
     split_features: Vec<usize>,
     thresholds: Vec<f64>,
+    decision_types: Vec<u8>,
     left_children: Vec<i32>,
     right_children: Vec<i32>,
     leaf_values: Vec<f64>,
+    internal_values: Option<Vec<f64>>,
+    internal_counts: Option<Vec<f64>>,
+    leaf_counts: Option<Vec<f64>>,
+    split_gains: Option<Vec<f64>>,
+    internal_weights: Option<Vec<f64>>,
+    leaf_weights: Option<Vec<f64>>,
 }
 
 impl From<LGBMTreeRecord> for Tree {
     fn from(record: LGBMTreeRecord) -> Self {
-        use ordered_float::NotNan;
-
         let mut nodes = Vec::new();
 
         let num_internal = record.split_features.len();
 
-        for (i, (&split_feature, &threshold, &left_child, &right_child)) in izip!(
+        for (i, (&split_feature, &threshold, &decision_type, &left_child, &right_child)) in izip!(
             &record.split_features,
             &record.thresholds,
+            &record.decision_types,
             &record.left_children,
             &record.right_children
         )
@@ -84,6 +91,11 @@ impl From<LGBMTreeRecord> for Tree {
                     Some(leaf_id)
                 },
                 value: NotNan::new(0.0).unwrap(),
+                default_left: Some(missing_goes_left(decision_type, threshold)),
+                cover: statistic(&record.internal_counts, i),
+                internal_value: statistic(&record.internal_values, i),
+                gain: statistic(&record.split_gains, i),
+                hessian: statistic(&record.internal_weights, i),
             };
             nodes.push(node);
         }
@@ -97,6 +109,11 @@ impl From<LGBMTreeRecord> for Tree {
                 left: None,
                 right: None,
                 value: NotNan::new(leaf_value).unwrap(),
+                default_left: None,
+                cover: statistic(&record.leaf_counts, i),
+                internal_value: None,
+                gain: None,
+                hessian: statistic(&record.leaf_weights, i),
             };
             nodes.push(leaf_node);
         }
@@ -105,12 +122,55 @@ impl From<LGBMTreeRecord> for Tree {
     }
 }
 
-fn read_lightgbm_txt(path: impl AsRef<Path>) -> Result<Vec<Vec<LGBMTreeRecord>>, LightGBMError> {
+/// Value of an optional per-node statistic for node `i`, e.g. sample counts used as covers.
+fn statistic(values: &Option<Vec<f64>>, i: usize) -> Option<NotNan<f64>> {
+    values
+        .as_ref()
+        .and_then(|values| values.get(i))
+        .and_then(|&value| NotNan::new(value).ok())
+}
+
+/// Direction of a missing (NaN) value at a split with the given `decision_type` bit field.
+fn missing_goes_left(decision_type: u8, threshold: f64) -> bool {
+    const DEFAULT_LEFT_MASK: u8 = 1 << 1;
+    const MISSING_TYPE_NONE: u8 = 0;
+
+    let missing_type = (decision_type >> 2) & 0b11;
+    if missing_type == MISSING_TYPE_NONE {
+        // without missing value handling NaN is converted to 0.0, which then goes wherever 0.0
+        // does under the strict comparison of the tree
+        0.0 < threshold
+    } else {
+        decision_type & DEFAULT_LEFT_MASK != 0
+    }
+}
+
+/// Feature type of a `feature_infos` entry: numerical features have a `[min:max]` range (or
+/// `none` when unused), categorical features list their categories as `1:3:5`.
+fn feature_type(feature_info: &str) -> &'static str {
+    if feature_info.starts_with('[') || feature_info == "none" {
+        "float"
+    } else {
+        "c"
+    }
+}
+
+type LGBMRecords = (Vec<Vec<LGBMTreeRecord>>, ModelMetadata);
+
+fn read_lightgbm_txt(path: impl AsRef<Path>) -> Result<LGBMRecords, LightGBMError> {
     let content = std::fs::read_to_string(path)?;
     let lines: Vec<&str> = content.lines().collect();
 
     let mut num_tree_per_iteration: Option<usize> = None;
     let mut tree_records: Vec<LGBMTreeRecord> = Vec::new();
+    let mut metadata = ModelMetadata {
+        source: Some(ModelSource {
+            library: "lightgbm".to_string(),
+            version: None,
+        }),
+        ..Default::default()
+    };
+    let mut in_header = true;
 
     for (line_idx, line) in lines.iter().enumerate() {
         let line = line.trim();
@@ -120,13 +180,39 @@ fn read_lightgbm_txt(path: impl AsRef<Path>) -> Result<Vec<Vec<LGBMTreeRecord>>,
         }
 
         if line.starts_with("Tree=") {
+            in_header = false;
             if let Some(record) = parse_tree_section(&lines, line_idx) {
                 tree_records.push(record);
             }
         } else if let Some((key, value)) = line.split_once('=')
-            && key == "num_tree_per_iteration"
+            && in_header
         {
-            num_tree_per_iteration = Some(value.parse()?);
+            match key {
+                "num_tree_per_iteration" => num_tree_per_iteration = Some(value.parse()?),
+                "version" => {
+                    if let Some(source) = &mut metadata.source {
+                        source.version = Some(value.to_string());
+                    }
+                }
+                "max_feature_idx" => {
+                    metadata.n_features = Some(value.parse::<usize>()? + 1);
+                }
+                "objective" => metadata.objective = Some(value.to_string()),
+                // 1-based, 0 without early stopping
+                "best_iteration" => {
+                    metadata.best_iteration = value.parse::<usize>()?.checked_sub(1);
+                }
+                "feature_names" => {
+                    metadata.feature_names = value.split_whitespace().map(String::from).collect();
+                }
+                "feature_infos" => {
+                    metadata.feature_types = value
+                        .split_whitespace()
+                        .map(|info| feature_type(info).to_string())
+                        .collect();
+                }
+                _ => {}
+            }
         }
     }
 
@@ -143,15 +229,22 @@ fn read_lightgbm_txt(path: impl AsRef<Path>) -> Result<Vec<Vec<LGBMTreeRecord>>,
         }
     }
 
-    Ok(result)
+    Ok((result, metadata))
 }
 
 fn parse_tree_section(lines: &[&str], start_idx: usize) -> Option<LGBMTreeRecord> {
     let mut split_features: Option<Vec<usize>> = None;
     let mut thresholds: Option<Vec<f64>> = None;
+    let mut decision_types: Option<Vec<u8>> = None;
     let mut left_children: Option<Vec<i32>> = None;
     let mut right_children: Option<Vec<i32>> = None;
     let mut leaf_values: Option<Vec<f64>> = None;
+    let mut internal_values: Option<Vec<f64>> = None;
+    let mut internal_counts: Option<Vec<f64>> = None;
+    let mut leaf_counts: Option<Vec<f64>> = None;
+    let mut split_gains: Option<Vec<f64>> = None;
+    let mut internal_weights: Option<Vec<f64>> = None;
+    let mut leaf_weights: Option<Vec<f64>> = None;
 
     let mut idx = start_idx + 1;
 
@@ -180,6 +273,14 @@ fn parse_tree_section(lines: &[&str], start_idx: usize) -> Option<LGBMTreeRecord
                             .collect::<Option<Vec<_>>>()?,
                     );
                 }
+                "decision_type" => {
+                    decision_types = Some(
+                        value
+                            .split_whitespace()
+                            .map(|s| s.parse().ok())
+                            .collect::<Option<Vec<_>>>()?,
+                    );
+                }
                 "left_child" => {
                     left_children = Some(
                         value
@@ -204,6 +305,54 @@ fn parse_tree_section(lines: &[&str], start_idx: usize) -> Option<LGBMTreeRecord
                             .collect::<Option<Vec<_>>>()?,
                     );
                 }
+                "internal_value" => {
+                    internal_values = Some(
+                        value
+                            .split_whitespace()
+                            .map(|s| s.parse().ok())
+                            .collect::<Option<Vec<_>>>()?,
+                    );
+                }
+                "internal_count" => {
+                    internal_counts = Some(
+                        value
+                            .split_whitespace()
+                            .map(|s| s.parse().ok())
+                            .collect::<Option<Vec<_>>>()?,
+                    );
+                }
+                "leaf_count" => {
+                    leaf_counts = Some(
+                        value
+                            .split_whitespace()
+                            .map(|s| s.parse().ok())
+                            .collect::<Option<Vec<_>>>()?,
+                    );
+                }
+                "split_gain" => {
+                    split_gains = Some(
+                        value
+                            .split_whitespace()
+                            .map(|s| s.parse().ok())
+                            .collect::<Option<Vec<_>>>()?,
+                    );
+                }
+                "internal_weight" => {
+                    internal_weights = Some(
+                        value
+                            .split_whitespace()
+                            .map(|s| s.parse().ok())
+                            .collect::<Option<Vec<_>>>()?,
+                    );
+                }
+                "leaf_weight" => {
+                    leaf_weights = Some(
+                        value
+                            .split_whitespace()
+                            .map(|s| s.parse().ok())
+                            .collect::<Option<Vec<_>>>()?,
+                    );
+                }
                 _ => {}
             }
         }
@@ -211,12 +360,22 @@ fn parse_tree_section(lines: &[&str], start_idx: usize) -> Option<LGBMTreeRecord
         idx += 1;
     }
 
+    let split_features = split_features?;
+    // older models omit `decision_type`, which then means numerical splits without missing values
+    let decision_types = decision_types.unwrap_or_else(|| vec![0; split_features.len()]);
     Some(LGBMTreeRecord {
-        split_features: split_features?,
+        split_features,
         thresholds: thresholds?,
+        decision_types,
         left_children: left_children?,
         right_children: right_children?,
         leaf_values: leaf_values?,
+        internal_values,
+        internal_counts,
+        leaf_counts,
+        split_gains,
+        internal_weights,
+        leaf_weights,
     })
 }
 
@@ -224,7 +383,11 @@ fn parse_tree_section(lines: &[&str], start_idx: usize) -> Option<LGBMTreeRecord
 mod tests {
     use std::path::PathBuf;
 
-    use crate::parser::{read_lightgbm_model, test_utils::test_model_prediction};
+    use super::*;
+    use crate::parser::{
+        read_lightgbm_model,
+        test_utils::{read_features, test_leaf_prediction, test_model_prediction},
+    };
 
     fn test_lightgbm(model_type: &str) {
         let manifest_dir = env!("CARGO_MANIFEST_DIR");
@@ -235,6 +398,8 @@ mod tests {
 
         test_model_prediction(&data_dir, &forest, 0.05)
             .unwrap_or_else(|e| panic!("LightGBM {model_type} model prediction test failed: {e}"));
+        test_leaf_prediction(&data_dir, &forest)
+            .unwrap_or_else(|e| panic!("LightGBM {model_type} leaf prediction test failed: {e}"));
     }
 
     #[test]
@@ -251,4 +416,102 @@ mod tests {
     fn test_multiclass_classification() {
         test_lightgbm("multiclass_classification");
     }
+
+    #[test]
+    fn test_tree_without_decision_type() {
+        let lines = [
+            "Tree=0",
+            "num_leaves=2",
+            "split_feature=0",
+            "threshold=0.5",
+            "left_child=-1",
+            "right_child=-2",
+            "leaf_value=1 2",
+            "",
+        ];
+        let tree = Tree::from(parse_tree_section(&lines, 0).unwrap());
+        assert_eq!(tree.predict(&[0.0]).into_inner(), 1.0);
+        assert_eq!(tree.predict(&[1.0]).into_inner(), 2.0);
+        // NaN is treated as 0.0, which goes left
+        assert_eq!(tree.predict(&[f64::NAN]).into_inner(), 1.0);
+    }
+
+    #[test]
+    fn test_missing_type_none_follows_zero() {
+        for threshold in [-1.0, 0.0, 1.0] {
+            assert_eq!(missing_goes_left(0, threshold), 0.0 < threshold);
+        }
+    }
+
+    #[test]
+    fn test_metadata() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let model_path = root.join("test_data/lightgbm/multiclass_classification/model.txt");
+        let metadata = read_lightgbm_model(&model_path).unwrap().metadata().clone();
+
+        let names: Vec<String> = (0..5).map(|i| format!("Column_{i}")).collect();
+        assert_eq!(metadata.feature_names, names);
+        assert_eq!(metadata.feature_types, vec!["float"; 5]);
+        assert_eq!(metadata.n_features, Some(5));
+        assert_eq!(
+            metadata.objective.as_deref(),
+            Some("multiclass num_class:3")
+        );
+        let source = metadata.source.unwrap();
+        assert_eq!(source.library, "lightgbm");
+        assert_eq!(source.version.as_deref(), Some("v4"));
+        assert_eq!(metadata.created_at, None);
+
+        // parsing is deterministic, so the binary encoding is reproducible
+        let binary = |model: MultiOutputForest| {
+            let mut bytes = Vec::new();
+            model.write_binary(&mut bytes).unwrap();
+            bytes
+        };
+        assert_eq!(
+            binary(read_lightgbm_model(&model_path).unwrap()),
+            binary(read_lightgbm_model(&model_path).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_best_iteration() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let model_path = root.join("test_data/lightgbm/regression/model.txt");
+        let model = read_lightgbm_model(&model_path).unwrap();
+        assert_eq!(model.metadata().best_iteration, None);
+
+        // LightGBM counts iterations from 1
+        let text = std::fs::read_to_string(&model_path).unwrap();
+        let text = text.replacen("version=v4\n", "version=v4\nbest_iteration=5\n", 1);
+        let path = std::env::temp_dir().join(format!("silva-best-{}.txt", std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        let model = read_lightgbm_model(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(model.unwrap().metadata().best_iteration, Some(4));
+    }
+
+    #[test]
+    fn test_predict_leaf() {
+        // leaves are leaf indices, which index the `leaf_value` arrays of the model file
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/lightgbm/multiclass_classification");
+        let model = read_lightgbm_model(data_dir.join("model.txt")).unwrap();
+        let text = std::fs::read_to_string(data_dir.join("model.txt")).unwrap();
+        let leaf_values: Vec<Vec<f64>> = text
+            .lines()
+            .filter_map(|line| line.strip_prefix("leaf_value="))
+            .map(|values| values.split(' ').map(|v| v.parse().unwrap()).collect())
+            .collect();
+
+        let n_classes = model.forests.len();
+        for x in read_features(&data_dir.join("X.csv")) {
+            let leaves = model.predict_leaf(&x).unwrap();
+            for (t, values) in leaf_values.iter().enumerate() {
+                let (class, position) = (t % n_classes, t / n_classes);
+                let prediction = model.forests[class].trees[position].predict(&x);
+                assert_eq!(values[leaves[class][position]], prediction.into_inner());
+            }
+        }
+    }
 }
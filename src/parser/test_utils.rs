@@ -2,7 +2,7 @@ use std::{fs::read_to_string, path::Path};
 
 use anyhow::Result as AnyResult;
 
-use crate::MultiOutputForest;
+use crate::{Float, MultiOutputForest};
 
 pub fn all_close(a: &[f64], b: &[f64], tol: f64) -> bool {
     if a.len() != b.len() {
@@ -44,13 +44,16 @@ pub fn read_labels_flattened(path: &Path) -> Vec<f64> {
         .collect()
 }
 
-pub fn test_model_prediction(
+pub fn test_model_prediction<F: Float>(
     data_dir: &Path,
-    forest: &MultiOutputForest,
+    forest: &MultiOutputForest<F>,
     tolerance: f64,
 ) -> AnyResult<()> {
     let x_path = data_dir.join("X.csv");
-    let x_data = read_features(&x_path);
+    let x_data: Vec<Vec<F>> = read_features(&x_path)
+        .into_iter()
+        .map(|x| x.into_iter().map(F::from_f64).collect())
+        .collect();
 
     let y_path = data_dir.join("y.csv");
     let y_true = read_labels_flattened(&y_path);
@@ -58,7 +61,7 @@ pub fn test_model_prediction(
     let y_pred = x_data
         .iter()
         .flat_map(|x| forest.predict(x))
-        .map(|v| v.into_inner())
+        .map(|v| v.into_inner().into())
         .collect::<Vec<f64>>();
 
     assert!(
@@ -68,3 +71,31 @@ pub fn test_model_prediction(
 
     Ok(())
 }
+
+/// Compare `predict_leaf` with the `pred_leaf=True` output of the source library in
+/// `leaves.csv`, which interleaves the outputs tree by tree.
+pub fn test_leaf_prediction<F: Float>(
+    data_dir: &Path,
+    forest: &MultiOutputForest<F>,
+) -> AnyResult<()> {
+    let x_data: Vec<Vec<F>> = read_features(&data_dir.join("X.csv"))
+        .into_iter()
+        .map(|x| x.into_iter().map(F::from_f64).collect())
+        .collect();
+    let leaves_true = read_to_string(data_dir.join("leaves.csv"))?;
+
+    let n_outputs = forest.forests.len();
+    for (x, line) in x_data.iter().zip(leaves_true.lines()) {
+        let leaves = forest.predict_leaf(x)?;
+        let expected = line
+            .split(',')
+            .map(|leaf| leaf.parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()?;
+        let interleaved: Vec<usize> = (0..expected.len())
+            .map(|t| leaves[t % n_outputs][t / n_outputs])
+            .collect();
+        assert_eq!(interleaved, expected, "Leaves differ for row {x:?}");
+    }
+
+    Ok(())
+}
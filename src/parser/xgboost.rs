@@ -1,11 +1,12 @@
 use std::{collections::HashMap, path::Path, vec};
 
 use itertools::izip;
+use ordered_float::NotNan;
 use serde::{Deserialize, Serialize};
 use serdeio::read_record_from_file;
 use thiserror::Error;
 
-use crate::{Forest, MultiOutputForest, Tree, TreeNode};
+use crate::{Forest, ModelMetadata, ModelSource, MultiOutputForest, Tree, TreeNode};
 
 /// Custom error types for XGBoost model parsing
 #[derive(Debug, Error)]
@@ -21,6 +22,8 @@ pub enum XGBoostError {
     InvalidBaseScore { value: String },
     #[error("Model parameter error: {parameter}")]
     InvalidParameters { parameter: String },
+    #[error("Malformed tree {tree}: {message}")]
+    MalformedTree { tree: i32, message: String },
     #[error("File read error: {source}")]
     FileRead {
         #[from]
@@ -36,6 +39,8 @@ pub struct XGBoostModelRecord {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LearnerRecord {
+    #[serde(default)]
+    pub attributes: HashMap<String, String>,
     pub feature_names: Option<Vec<String>>,
     pub feature_types: Option<Vec<String>>,
     pub gradient_booster: GradientBooster,
@@ -65,10 +70,14 @@ pub struct GbtreeModelRecord {
 }
 
 impl GbtreeModelRecord {
-    pub fn parse(self) -> (Vec<Tree>, Vec<usize>) {
-        let trees = self.trees.into_iter().map(|tree| tree.parse()).collect();
+    pub fn parse(self) -> Result<(Vec<Tree>, Vec<usize>), XGBoostError> {
+        let trees = self
+            .trees
+            .into_iter()
+            .map(TreeRecord::parse)
+            .collect::<Result<_, _>>()?;
         let tree_info = self.tree_info;
-        (trees, tree_info)
+        Ok((trees, tree_info))
     }
 }
 
@@ -99,21 +108,101 @@ pub struct TreeRecord {
 }
 
 impl TreeRecord {
-    pub fn parse(self) -> Tree {
+    fn malformed(&self, message: impl Into<String>) -> XGBoostError {
+        XGBoostError::MalformedTree {
+            tree: self.id,
+            message: message.into(),
+        }
+    }
+
+    /// Cover-weighted mean of the leaf values below every node, `None` where a cover is missing.
+    ///
+    /// `base_weights` of split nodes lack the learning rate, which is not saved with the model,
+    /// so split node values are computed like XGBoost does for its approximate contributions.
+    /// XGBoost numbers children after their parents, so one pass from the last node computes
+    /// every mean from those of the children, and rejects cycles.
+    fn node_means(&self) -> Result<Vec<Option<f64>>, XGBoostError> {
+        let n = self.left_children.len();
+        let mut means: Vec<Option<f64>> = vec![None; n];
+        for i in (0..n).rev() {
+            let left = self.left_children[i];
+            let right = self.right_children.get(i).copied();
+            if left < 0 && right.is_some_and(|right| right < 0) {
+                means[i] = self.split_conditions.get(i).copied();
+                continue;
+            }
+            let child = |child: Option<i32>| {
+                child
+                    .and_then(|child| usize::try_from(child).ok())
+                    .filter(|&child| child > i && child < n)
+                    .ok_or_else(|| self.malformed(format!("invalid children of node {i}")))
+            };
+            let (left, right) = (child(Some(left))?, child(right)?);
+            let cover = |j: usize| self.sum_hessian.get(j).copied();
+            means[i] = (|| {
+                Some((cover(left)? * means[left]? + cover(right)? * means[right]?) / cover(i)?)
+            })();
+        }
+        Ok(means)
+    }
+
+    pub fn parse(self) -> Result<Tree, XGBoostError> {
+        let n = self.left_children.len();
+        let lengths = [
+            self.right_children.len(),
+            self.split_indices.len(),
+            self.split_conditions.len(),
+            self.default_left.len(),
+            self.base_weights.len(),
+            self.sum_hessian.len(),
+            self.loss_changes.len(),
+        ];
+        if lengths.iter().any(|&len| len != n) {
+            return Err(self.malformed("node arrays differ in length"));
+        }
+        let internal_values: Vec<Option<f64>> = self
+            .node_means()?
+            .into_iter()
+            .zip(&self.left_children)
+            .map(|(mean, &left)| if left < 0 { None } else { mean })
+            .collect();
+        let tree = self.id;
         let mut nodes = Vec::new();
-        for (i, (_value, left, right, split_index, split_condition)) in izip!(
+        for (
+            i,
+            (
+                _value,
+                left,
+                right,
+                split_index,
+                split_condition,
+                default_left,
+                cover,
+                internal_value,
+                gain,
+            ),
+        ) in izip!(
             self.base_weights,
             self.left_children,
             self.right_children,
             self.split_indices,
-            self.split_conditions
+            self.split_conditions,
+            self.default_left,
+            self.sum_hessian,
+            internal_values,
+            self.loss_changes
         )
         .enumerate()
         {
+            let split_condition =
+                NotNan::new(split_condition).map_err(|_| XGBoostError::MalformedTree {
+                    tree,
+                    message: format!("NaN split condition at node {i}"),
+                })?;
             let node = TreeNode {
                 id: i,
                 split_index: split_index as usize,
-                split_condition: ordered_float::NotNan::new(split_condition).unwrap(),
+                split_condition,
                 left: if left > 0 { Some(left as usize) } else { None },
                 right: if right > 0 {
                     Some(right as usize)
@@ -122,13 +211,23 @@ impl TreeRecord {
                 },
                 // surprisingly, the leaf value is taken from split_conditions, not base_weights
                 // check https://github.com/dmlc/xgboost/issues/11521
-                value: ordered_float::NotNan::new(split_condition).unwrap(),
+                value: split_condition,
+                default_left: Some(default_left != 0),
+                cover: NotNan::new(cover).ok(),
+                internal_value: internal_value.and_then(|v| NotNan::new(v).ok()),
+                gain: if left > 0 {
+                    NotNan::new(gain).ok()
+                } else {
+                    None
+                },
+                // XGBoost's cover is already the hessian sum, which is not repeated here
+                hessian: None,
             };
 
             nodes.push(node);
         }
 
-        Tree::from_nodes(nodes)
+        Ok(Tree::from_nodes(nodes))
     }
 }
 
@@ -190,8 +289,17 @@ fn logit(p: f64) -> f64 {
 }
 
 pub fn parse_xgboost_model(record: XGBoostModelRecord) -> Result<MultiOutputForest, XGBoostError> {
-    let (trees, tree_info) = match record.learner.gradient_booster {
-        GradientBooster::Gbtree { model } => model.parse(),
+    let (trees, tree_info, trees_per_iteration) = match record.learner.gradient_booster {
+        GradientBooster::Gbtree { model } => {
+            let num_parallel_tree = &model.gbtree_model_param.num_parallel_tree;
+            let trees_per_iteration = num_parallel_tree.parse::<usize>().map_err(|_| {
+                XGBoostError::InvalidParameters {
+                    parameter: format!("num_parallel_tree: {num_parallel_tree}"),
+                }
+            })?;
+            let (trees, tree_info) = model.parse()?;
+            (trees, tree_info, trees_per_iteration)
+        }
         GradientBooster::Gblinear { .. } => {
             return Err(XGBoostError::UnsupportedBooster {
                 booster: "gblinear".to_string(),
@@ -248,7 +356,43 @@ pub fn parse_xgboost_model(record: XGBoostModelRecord) -> Result<MultiOutputFore
         .map(|(trees, base_value)| Forest::new(base_value, trees))
         .collect();
 
-    Ok(MultiOutputForest::new(forests))
+    let n_features = record
+        .learner
+        .learner_model_param
+        .num_feature
+        .map(|n| {
+            n.parse().map_err(|_| XGBoostError::InvalidParameters {
+                parameter: format!("num_feature: {n}"),
+            })
+        })
+        .transpose()?;
+    let best_iteration = record
+        .learner
+        .attributes
+        .get("best_iteration")
+        .map(|i| {
+            i.parse().map_err(|_| XGBoostError::InvalidParameters {
+                parameter: format!("best_iteration: {i}"),
+            })
+        })
+        .transpose()?;
+    let [major, minor, patch] = record.version;
+    let metadata = ModelMetadata {
+        feature_names: record.learner.feature_names.unwrap_or_default(),
+        feature_types: record.learner.feature_types.unwrap_or_default(),
+        n_features,
+        objective: Some(objective_name),
+        source: Some(ModelSource {
+            library: "xgboost".to_string(),
+            version: Some(format!("{major}.{minor}.{patch}")),
+        }),
+        trees_per_iteration: Some(trees_per_iteration),
+        best_iteration,
+        created_at: None,
+        attributes: record.learner.attributes.into_iter().collect(),
+    };
+
+    Ok(MultiOutputForest::new(forests).with_metadata(metadata))
 }
 
 pub fn read_xgboost_model(path: impl AsRef<Path>) -> Result<MultiOutputForest, XGBoostError> {
@@ -269,7 +413,10 @@ mod tests {
     use std::path::PathBuf;
 
     use super::*;
-    use crate::parser::{read_xgboost_model, test_utils::test_model_prediction};
+    use crate::parser::{
+        read_xgboost_model,
+        test_utils::{read_features, test_leaf_prediction, test_model_prediction},
+    };
 
     fn test_xgboost(model_type: &str) {
         let manifest_dir = env!("CARGO_MANIFEST_DIR");
@@ -284,6 +431,9 @@ mod tests {
                 model_type, data_dir, e
             )
         });
+        // XGBoost rounds inputs to f32
+        test_leaf_prediction(&data_dir, &forest.cast::<f32>())
+            .unwrap_or_else(|e| panic!("XGBoost {model_type} leaf prediction test failed: {e}"));
     }
 
     #[test]
@@ -291,6 +441,52 @@ mod tests {
         test_xgboost("regression");
     }
 
+    #[test]
+    fn test_f32_precision() {
+        // XGBoost evaluates in float32, so the f32 model reproduces its margins much more closely
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        for model_type in [
+            "regression",
+            "binary_classification",
+            "multiclass_classification",
+        ] {
+            let data_dir = root.join(format!("test_data/xgboost/{model_type}"));
+            let forest = read_xgboost_model(data_dir.join("model.json"))
+                .expect("Failed to load model")
+                .cast::<f32>();
+            test_model_prediction(&data_dir, &forest, 1e-4)
+                .unwrap_or_else(|e| panic!("XGBoost {model_type} f32 prediction test failed: {e}"));
+        }
+    }
+
+    #[test]
+    fn test_f32_exact_threshold() {
+        // XGBoost rounds inputs to float32 before comparing them to its float32 thresholds, so a
+        // value just below a threshold in f64 that rounds onto it goes right
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let model = read_xgboost_model(root.join("test_data/xgboost/regression/model.json"))
+            .expect("Failed to load model");
+        let model32 = model.cast::<f32>();
+        for (tree, tree32) in model.forests[0].trees.iter().zip(&model32.forests[0].trees) {
+            for (node, node32) in tree.node_map.values().zip(tree32.node_map.values()) {
+                let threshold = node32.split_condition.into_inner();
+                if node.is_leaf() || threshold == 0.0 {
+                    continue;
+                }
+                assert_eq!(threshold, node.split_condition.into_inner() as f32);
+                let below = f64::from(threshold) - f64::from(threshold.abs() * f32::EPSILON) / 8.0;
+                assert_eq!(below as f32, threshold);
+                for x in [threshold, below as f32] {
+                    assert_eq!(node32.next_node_id(x), node32.right);
+                }
+                let just_below = f32::from_bits(threshold.to_bits() - 1);
+                if threshold > 0.0 {
+                    assert_eq!(node32.next_node_id(just_below), node32.left);
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_binary_classification() {
         test_xgboost("binary_classification");
@@ -301,12 +497,98 @@ mod tests {
         test_xgboost("multiclass_classification");
     }
 
+    #[test]
+    fn test_metadata() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let model_path = root.join("test_data/xgboost/binary_classification/model.json");
+        let metadata = read_xgboost_model(model_path).unwrap().metadata().clone();
+
+        assert_eq!(metadata.n_features, Some(5));
+        assert_eq!(metadata.objective.as_deref(), Some("binary:logistic"));
+        let source = metadata.source.unwrap();
+        assert_eq!(source.library, "xgboost");
+        assert_eq!(source.version.as_deref(), Some("3.1.2"));
+        assert_eq!(metadata.created_at, None);
+    }
+
+    #[test]
+    fn test_best_iteration() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let model_path = root.join("test_data/xgboost/regression/model.json");
+        let mut record: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&model_path).unwrap()).unwrap();
+        let metadata = read_xgboost_model(&model_path).unwrap().metadata().clone();
+        assert_eq!(metadata.best_iteration, None);
+        assert_eq!(metadata.trees_per_iteration, Some(1));
+
+        record["learner"]["attributes"]["best_iteration"] = "4".into();
+        let model = parse_xgboost_model(serde_json::from_value(record).unwrap()).unwrap();
+        assert_eq!(model.metadata().best_iteration, Some(4));
+    }
+
+    #[test]
+    fn test_predict_leaf() {
+        // leaves are node ids, which index the node arrays of the model file
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/xgboost/multiclass_classification");
+        let model = read_xgboost_model(data_dir.join("model.json")).unwrap();
+        let record: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(data_dir.join("model.json")).unwrap())
+                .unwrap();
+        let booster = &record["learner"]["gradient_booster"]["model"];
+        let trees = booster["trees"].as_array().unwrap();
+        let tree_info = booster["tree_info"].as_array().unwrap();
+
+        for x in read_features(&data_dir.join("X.csv")) {
+            let leaves = model.predict_leaf(&x).unwrap();
+            let mut positions = vec![0; leaves.len()];
+            for (tree, class) in trees.iter().zip(tree_info) {
+                let class = class.as_u64().unwrap() as usize;
+                let leaf = leaves[class][positions[class]];
+                assert_eq!(tree["left_children"][leaf], -1);
+                let prediction = model.forests[class].trees[positions[class]].predict(&x);
+                assert_eq!(tree["split_conditions"][leaf], prediction.into_inner());
+                positions[class] += 1;
+            }
+        }
+    }
+
     // Error handling tests
+    #[test]
+    fn test_malformed_tree() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let path = root.join("test_data/xgboost/regression/model.json");
+        let tree = || {
+            let record: XGBoostModelRecord = read_record_from_file(&path).unwrap();
+            let GradientBooster::Gbtree { mut model } = record.learner.gradient_booster else {
+                panic!("expected a gbtree model");
+            };
+            model.trees.swap_remove(0)
+        };
+        assert!(tree().parse().is_ok());
+
+        let mut cyclic = tree();
+        cyclic.left_children[1] = 0;
+        let mut out_of_bounds = tree();
+        out_of_bounds.right_children[0] = 100_000;
+        let mut single_child = tree();
+        single_child.right_children[0] = -1;
+        let mut truncated = tree();
+        truncated.split_conditions.pop();
+        for tree in [cyclic, out_of_bounds, single_child, truncated] {
+            assert!(matches!(
+                tree.parse(),
+                Err(XGBoostError::MalformedTree { .. })
+            ));
+        }
+    }
+
     #[test]
     fn test_parse_xgboost_model_unsupported_booster_gblinear() {
         let model = XGBoostModelRecord {
             version: [1, 0, 0],
             learner: LearnerRecord {
+                attributes: HashMap::new(),
                 feature_names: None,
                 feature_types: None,
                 gradient_booster: GradientBooster::Gblinear {
@@ -339,6 +621,7 @@ mod tests {
         let model = XGBoostModelRecord {
             version: [1, 0, 0],
             learner: LearnerRecord {
+                attributes: HashMap::new(),
                 feature_names: None,
                 feature_types: None,
                 gradient_booster: GradientBooster::Dart {
@@ -379,6 +662,7 @@ mod tests {
         let model = XGBoostModelRecord {
             version: [1, 0, 0],
             learner: LearnerRecord {
+                attributes: HashMap::new(),
                 feature_names: None,
                 feature_types: None,
                 gradient_booster: GradientBooster::Gbtree {
@@ -419,6 +703,7 @@ mod tests {
         let model = XGBoostModelRecord {
             version: [1, 0, 0],
             learner: LearnerRecord {
+                attributes: HashMap::new(),
                 feature_names: None,
                 feature_types: None,
                 gradient_booster: GradientBooster::Gbtree {
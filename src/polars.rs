@@ -0,0 +1,289 @@
+//! Predictions on Polars data frames, enabled by the `polars` feature.
+
+use polars::prelude::*;
+use rustc_hash::FxHashMap;
+use thiserror::Error;
+
+use crate::{
+    Error, Float, MultiOutputForest,
+    features::{CategoryCodes, FeatureError, FeatureMapper},
+};
+
+/// Custom error types for Polars input
+#[derive(Debug, Error)]
+pub enum DataFrameError {
+    #[error("Polars error: {source}")]
+    Polars {
+        #[from]
+        source: PolarsError,
+    },
+    #[error("The model has no feature names")]
+    MissingFeatureNames,
+    #[error("Feature error: {source}")]
+    Feature { source: FeatureError },
+    #[error("Unsupported type {data_type} of column {name}")]
+    UnsupportedColumn { name: String, data_type: String },
+    #[error("Expected {expected} output names, got {actual}")]
+    OutputNames { expected: usize, actual: usize },
+    #[error("No category codes for categorical column {name}")]
+    MissingCategoryCodes { name: String },
+    #[error("Unknown category {category} in column {name}")]
+    UnknownCategory { name: String, category: String },
+    #[error("Prediction failed at row {row}: {source}")]
+    Prediction {
+        row: usize,
+        #[source]
+        source: Error,
+    },
+}
+
+/// Values of a column as `f64`, with nulls as NaN.
+///
+/// Categorical, enum and string columns are encoded with the category codes of the feature:
+/// the physical codes of a categorical depend on the global string cache, not on training.
+fn column_values(
+    column: &Column,
+    codes: Option<&FxHashMap<String, usize>>,
+) -> Result<Vec<f64>, DataFrameError> {
+    let dtype = column.dtype();
+    let name = || column.name().to_string();
+    if dtype.is_categorical() || dtype.is_enum() || dtype.is_string() {
+        let Some(codes) = codes else {
+            if dtype.is_string() {
+                return Err(DataFrameError::UnsupportedColumn {
+                    name: name(),
+                    data_type: dtype.to_string(),
+                });
+            }
+            return Err(DataFrameError::MissingCategoryCodes { name: name() });
+        };
+        let strings = column.cast(&DataType::String)?;
+        return strings
+            .str()?
+            .iter()
+            .map(|category| match category {
+                None => Ok(f64::NAN),
+                Some(category) => codes.get(category).map(|&code| code as f64).ok_or_else(|| {
+                    DataFrameError::UnknownCategory {
+                        name: name(),
+                        category: category.to_string(),
+                    }
+                }),
+            })
+            .collect();
+    }
+    let numeric = if dtype.is_primitive_numeric() || dtype.is_bool() {
+        column.clone()
+    } else {
+        return Err(DataFrameError::UnsupportedColumn {
+            name: column.name().to_string(),
+            data_type: dtype.to_string(),
+        });
+    };
+    let values = numeric.cast(&DataType::Float64)?;
+    Ok(values
+        .f64()?
+        .iter()
+        .map(|v| v.unwrap_or(f64::NAN))
+        .collect())
+}
+
+impl<F: Float> MultiOutputForest<F> {
+    /// Score every row of `df` and append one prediction column per output.
+    ///
+    /// Columns are matched to the model's feature names and other columns are ignored. Nulls are
+    /// missing values. Prediction columns are named `pred_0..pred_k` unless `output_names` are
+    /// given, e.g. class names. Categorical columns are refused, see
+    /// [`Self::predict_dataframe_with_categories`].
+    ///
+    /// Rows are predicted with [`Self::try_predict`], so a null at a split without default
+    /// direction is a [`DataFrameError::Prediction`].
+    pub fn predict_dataframe(
+        &self,
+        df: &mut DataFrame,
+        output_names: Option<&[&str]>,
+    ) -> Result<(), DataFrameError> {
+        self.predict_dataframe_with_categories(df, output_names, &CategoryCodes::new())
+    }
+
+    /// Like [`Self::predict_dataframe`], encoding categorical, enum and string columns with the
+    /// category codes the model was trained with. Categories without a code are errors.
+    pub fn predict_dataframe_with_categories(
+        &self,
+        df: &mut DataFrame,
+        output_names: Option<&[&str]>,
+        categories: &CategoryCodes,
+    ) -> Result<(), DataFrameError> {
+        let n_outputs = self.forests.len();
+        let output_names: Vec<String> = match output_names {
+            Some(names) if names.len() != n_outputs => {
+                return Err(DataFrameError::OutputNames {
+                    expected: n_outputs,
+                    actual: names.len(),
+                });
+            }
+            Some(names) => names.iter().map(|name| name.to_string()).collect(),
+            None => (0..n_outputs).map(|i| format!("pred_{i}")).collect(),
+        };
+
+        let mapper = FeatureMapper::from_model(self).map_err(|e| match e {
+            FeatureError::MissingFeatureNames => DataFrameError::MissingFeatureNames,
+            source => DataFrameError::Feature { source },
+        })?;
+        let columns = self
+            .metadata()
+            .feature_names
+            .iter()
+            .map(|name| column_values(df.column(name)?, categories.of(name)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut outputs = vec![Vec::with_capacity(df.height()); n_outputs];
+        let mut row = vec![F::nan(); mapper.n_features()];
+        for r in 0..df.height() {
+            for (value, column) in row.iter_mut().zip(&columns) {
+                *value = F::from_f64(column[r]);
+            }
+            let predictions = self
+                .try_predict(&row)
+                .map_err(|source| DataFrameError::Prediction { row: r, source })?;
+            for (output, prediction) in outputs.iter_mut().zip(predictions) {
+                output.push(prediction.into_inner().into());
+            }
+        }
+
+        for (name, output) in output_names.into_iter().zip(outputs) {
+            df.with_column(Column::new(name.into(), output))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{
+        Forest, Tree, TreeNode,
+        parser::{read_lightgbm_model, test_utils::read_features},
+    };
+
+    #[test]
+    fn test_predict_dataframe() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/lightgbm/multiclass_classification");
+        let model = read_lightgbm_model(data_dir.join("model.txt")).unwrap();
+
+        let mut rows = read_features(&data_dir.join("X.csv"));
+        for (r, row) in rows.iter_mut().enumerate() {
+            if r % 4 == 0 {
+                row[r % 5] = f64::NAN;
+            }
+        }
+        let column = |i: usize| -> Column {
+            let values: Vec<Option<f64>> = rows
+                .iter()
+                .map(|row| Some(row[i]).filter(|v| !v.is_nan()))
+                .collect();
+            Column::new(format!("Column_{i}").into(), values)
+        };
+        let ids: Vec<String> = (0..rows.len()).map(|i| format!("id{i}")).collect();
+        let mut df = DataFrame::new(vec![
+            column(3),
+            Column::new("id".into(), ids),
+            column(0),
+            column(4),
+            column(1),
+            column(2),
+        ])
+        .unwrap();
+
+        model
+            .predict_dataframe(&mut df, Some(&["a", "b", "c"]))
+            .unwrap();
+        model.predict_dataframe(&mut df, None).unwrap();
+        assert_eq!(df.width(), 12);
+        for (r, row) in rows.iter().enumerate() {
+            for (i, expected) in model.predict(row).into_iter().enumerate() {
+                let expected = Some(expected.into_inner());
+                let name = ["a", "b", "c"][i];
+                assert_eq!(df.column(name).unwrap().f64().unwrap().get(r), expected);
+                let name = format!("pred_{i}");
+                assert_eq!(df.column(&name).unwrap().f64().unwrap().get(r), expected);
+            }
+        }
+
+        assert!(matches!(
+            model.predict_dataframe(&mut df, Some(&["a"])),
+            Err(DataFrameError::OutputNames { .. })
+        ));
+        let mut df = df.drop("Column_2").unwrap();
+        assert!(matches!(
+            model.predict_dataframe(&mut df, None),
+            Err(DataFrameError::Polars { .. })
+        ));
+    }
+
+    #[test]
+    fn test_categorical_column() {
+        let column = Column::new("color".into(), ["red", "blue", "red"])
+            .cast(&DataType::from_categories(Categories::global()))
+            .unwrap();
+        assert!(matches!(
+            column_values(&column, None),
+            Err(DataFrameError::MissingCategoryCodes { .. })
+        ));
+
+        // codes come from the mapping, not from the order categories were seen
+        let categories = CategoryCodes::new().with_feature("color", ["green", "blue", "red"]);
+        let codes = categories.of("color");
+        assert_eq!(column_values(&column, codes).unwrap(), [2.0, 1.0, 2.0]);
+        let strings = Column::new("color".into(), [Some("blue"), None]);
+        let values = column_values(&strings, codes).unwrap();
+        assert_eq!(values[0], 1.0);
+        assert!(values[1].is_nan());
+
+        let unknown = Column::new("color".into(), ["purple"]);
+        assert!(matches!(
+            column_values(&unknown, codes),
+            Err(DataFrameError::UnknownCategory { .. })
+        ));
+        assert!(matches!(
+            column_values(&strings, None),
+            Err(DataFrameError::UnsupportedColumn { .. })
+        ));
+    }
+
+    #[test]
+    fn test_null_without_default_direction() {
+        let tree = Tree::from_nodes(vec![
+            TreeNode::split(0, 1, 1.0, 1, 2),
+            TreeNode::leaf(1, 1.0),
+            TreeNode::leaf(2, 2.0),
+        ]);
+        let mut model = MultiOutputForest::new(vec![Forest::new(0.0, vec![tree])]);
+        model.metadata_mut().feature_names = vec!["a".to_string(), "b".to_string()];
+        let mut df = DataFrame::new(vec![
+            Column::new("a".into(), [0.0, 0.0]),
+            Column::new("b".into(), [Some(3.0), None]),
+        ])
+        .unwrap();
+        assert!(matches!(
+            model.predict_dataframe(&mut df, None),
+            Err(DataFrameError::Prediction {
+                row: 1,
+                source: Error::MissingValue {
+                    feature: 1,
+                    node: 0
+                }
+            })
+        ));
+
+        let mut df = df.head(Some(1));
+        model.predict_dataframe(&mut df, None).unwrap();
+        assert_eq!(
+            df.column("pred_0").unwrap().f64().unwrap().get(0),
+            Some(2.0)
+        );
+    }
+}
@@ -0,0 +1,497 @@
+use std::fmt::Debug;
+
+use ordered_float::NotNan;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use thiserror::Error;
+
+use crate::{Float, MultiOutputForest, Tree, native::FormatVersion};
+
+/// Unsigned integer type used for bin indices and leaf codes, implemented for `u8` and `u16`.
+pub trait BinIndex:
+    Copy + Ord + Debug + Send + Sync + Serialize + DeserializeOwned + 'static
+{
+    /// Reserved for missing (NaN) inputs, never a valid bin or code.
+    const MISSING: Self;
+
+    fn from_usize(v: usize) -> Self;
+    fn to_usize(self) -> usize;
+}
+
+impl BinIndex for u8 {
+    const MISSING: Self = u8::MAX;
+
+    fn from_usize(v: usize) -> Self {
+        v as u8
+    }
+
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+}
+
+impl BinIndex for u16 {
+    const MISSING: Self = u16::MAX;
+
+    fn from_usize(v: usize) -> Self {
+        v as u16
+    }
+
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum QuantizeError {
+    #[error(
+        "Feature {feature} has {count} distinct thresholds, more than the {max} the bin index type can hold"
+    )]
+    TooManyThresholds {
+        feature: usize,
+        count: usize,
+        max: usize,
+    },
+    #[error("Invalid codebook size {size}, expected a value between 1 and {max}")]
+    InvalidCodebookSize { size: usize, max: usize },
+}
+
+/// A node of a [`QuantizedForest`] tree, stored in pre-order so the left child directly follows
+/// its parent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
+struct QuantizedNode<B: BinIndex> {
+    #[serde(rename = "f")]
+    feature: u32,
+    /// Inputs with a bin up to this one go left. Leaf code for leaves.
+    #[serde(rename = "b")]
+    bin: B,
+    /// Position of the right child, 0 for leaves.
+    #[serde(rename = "r")]
+    right: u32,
+    #[serde(rename = "dl")]
+    default_left: bool,
+}
+
+impl<B: BinIndex> QuantizedNode<B> {
+    fn is_leaf(&self) -> bool {
+        self.right == 0
+    }
+}
+
+/// Compressed form of a [`MultiOutputForest`] for memory constrained deployments.
+///
+/// Split thresholds are replaced by per-feature bin tables, so trees compare small bin indices
+/// instead of floats and each input row is binned only once. Binning is lossless. Leaf values are
+/// replaced by indices into a shared codebook, which is where all the approximation error comes
+/// from; [`QuantizedForest::max_errors`] bounds it.
+///
+/// Serialized with a format version, see [`crate::NATIVE_FORMAT_VERSION`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct QuantizedForest<B: BinIndex = u8, F: Float = f64> {
+    #[serde(default)]
+    version: FormatVersion,
+    /// Sorted distinct split thresholds of each feature.
+    #[serde(rename = "bt")]
+    bin_thresholds: Vec<Vec<F>>,
+    /// Representative leaf values, indexed by leaf codes.
+    #[serde(rename = "cb")]
+    codebook: Vec<F>,
+    #[serde(rename = "bv")]
+    base_values: Vec<F>,
+    /// Trees of each output.
+    #[serde(rename = "t")]
+    trees: Vec<Vec<Vec<QuantizedNode<B>>>>,
+    /// Upper bound of the absolute prediction error of each output.
+    #[serde(rename = "me")]
+    max_errors: Vec<F>,
+}
+
+impl<B: BinIndex, F: Float> QuantizedForest<B, F> {
+    /// Quantize `model`, representing its leaf values with at most `codebook_size` distinct values.
+    pub fn new(model: &MultiOutputForest<F>, codebook_size: usize) -> Result<Self, QuantizeError> {
+        // the largest index is reserved for missing values
+        let max_codes = B::MISSING.to_usize();
+        if codebook_size == 0 || codebook_size > max_codes {
+            return Err(QuantizeError::InvalidCodebookSize {
+                size: codebook_size,
+                max: max_codes,
+            });
+        }
+
+        let trees = model.forests.iter().flat_map(|forest| &forest.trees);
+
+        let mut bin_thresholds: Vec<Vec<NotNan<F>>> = Vec::new();
+        let mut leaf_values: Vec<NotNan<F>> = Vec::new();
+        for tree in trees.clone() {
+            for node in tree.node_map.values() {
+                if node.is_leaf() {
+                    leaf_values.push(node.value);
+                } else {
+                    if bin_thresholds.len() <= node.split_index {
+                        bin_thresholds.resize(node.split_index + 1, Vec::new());
+                    }
+                    bin_thresholds[node.split_index].push(node.split_condition);
+                }
+            }
+        }
+        for (feature, thresholds) in bin_thresholds.iter_mut().enumerate() {
+            thresholds.sort();
+            thresholds.dedup();
+            // a row can fall into any of the `count + 1` bins, and `MISSING` must stay free
+            if thresholds.len() >= max_codes {
+                return Err(QuantizeError::TooManyThresholds {
+                    feature,
+                    count: thresholds.len(),
+                    max: max_codes - 1,
+                });
+            }
+        }
+
+        let codebook = build_codebook(leaf_values, codebook_size);
+
+        let mut max_errors = Vec::new();
+        let mut quantized_trees = Vec::new();
+        for forest in &model.forests {
+            let mut max_error = F::zero();
+            let mut output_trees = Vec::new();
+            for tree in &forest.trees {
+                let mut nodes = Vec::new();
+                let tree_error =
+                    quantize_node(tree, tree.root, &bin_thresholds, &codebook, &mut nodes);
+                max_error = max_error + tree_error;
+                output_trees.push(nodes);
+            }
+            max_errors.push(max_error);
+            quantized_trees.push(output_trees);
+        }
+
+        Ok(Self {
+            version: FormatVersion,
+            bin_thresholds: bin_thresholds
+                .into_iter()
+                .map(|thresholds| thresholds.into_iter().map(NotNan::into_inner).collect())
+                .collect(),
+            codebook: codebook.into_iter().map(NotNan::into_inner).collect(),
+            base_values: model.forests.iter().map(|f| f.base_value).collect(),
+            trees: quantized_trees,
+            max_errors,
+        })
+    }
+
+    /// Upper bound of the absolute difference to the original model's prediction, per output.
+    ///
+    /// The bound sums the worst leaf error of every tree, so it is reached only if those leaves
+    /// can be hit by the same input.
+    pub fn max_errors(&self) -> &[F] {
+        &self.max_errors
+    }
+
+    /// Map a row of features to bin indices, NaN becoming [`BinIndex::MISSING`].
+    pub fn bin_row(&self, x: &[F]) -> Vec<B> {
+        self.bin_thresholds
+            .iter()
+            .enumerate()
+            .map(|(feature, thresholds)| {
+                let v = x[feature];
+                if v.is_nan() {
+                    B::MISSING
+                } else {
+                    B::from_usize(thresholds.partition_point(|&t| t <= v))
+                }
+            })
+            .collect()
+    }
+
+    pub fn predict(&self, x: &[F]) -> Vec<NotNan<F>> {
+        self.predict_binned(&self.bin_row(x))
+    }
+
+    /// Predict from a row already mapped by [`QuantizedForest::bin_row`].
+    pub fn predict_binned(&self, bins: &[B]) -> Vec<NotNan<F>> {
+        self.trees
+            .iter()
+            .zip(&self.base_values)
+            .map(|(trees, &base_value)| {
+                let sum: F = trees
+                    .iter()
+                    .map(|nodes| self.codebook[self.leaf_code(nodes, bins).to_usize()])
+                    .sum();
+                NotNan::new(base_value + sum).unwrap()
+            })
+            .collect()
+    }
+
+    fn leaf_code(&self, nodes: &[QuantizedNode<B>], bins: &[B]) -> B {
+        let mut pos = 0;
+        let mut node = &nodes[pos];
+        while !node.is_leaf() {
+            let bin = bins[node.feature as usize];
+            // `bin <= node.bin` is equivalent to `x < threshold` of the original split
+            let go_left = if bin == B::MISSING {
+                node.default_left
+            } else {
+                bin <= node.bin
+            };
+            pos = if go_left {
+                pos + 1
+            } else {
+                node.right as usize
+            };
+            node = &nodes[pos];
+        }
+        node.bin
+    }
+}
+
+/// Append the subtree rooted at `id` in pre-order and return its largest leaf error.
+fn quantize_node<B: BinIndex, F: Float>(
+    tree: &Tree<F>,
+    id: usize,
+    bin_thresholds: &[Vec<NotNan<F>>],
+    codebook: &[NotNan<F>],
+    nodes: &mut Vec<QuantizedNode<B>>,
+) -> F {
+    let node = &tree.node_map[&id];
+    let pos = nodes.len();
+    match (node.left, node.right) {
+        (Some(left), Some(right)) => {
+            let thresholds = &bin_thresholds[node.split_index];
+            // position of the threshold, i.e. the last bin entirely below it
+            let bin = thresholds
+                .binary_search(&node.split_condition)
+                .expect("every threshold has a bin");
+            nodes.push(QuantizedNode {
+                feature: node.split_index as u32,
+                bin: B::from_usize(bin),
+                right: 0,
+                default_left: node.default_left.unwrap_or(false),
+            });
+            let left_error = quantize_node(tree, left, bin_thresholds, codebook, nodes);
+            nodes[pos].right = nodes.len() as u32;
+            let right_error = quantize_node(tree, right, bin_thresholds, codebook, nodes);
+            left_error.max(right_error)
+        }
+        _ => {
+            let code = nearest(codebook, node.value);
+            nodes.push(QuantizedNode {
+                feature: 0,
+                bin: B::from_usize(code),
+                right: 0,
+                default_left: false,
+            });
+            (codebook[code] - node.value).into_inner().abs()
+        }
+    }
+}
+
+/// 1D k-means (Lloyd's algorithm) over all leaf values, initialized at quantiles.
+fn build_codebook<F: Float>(mut values: Vec<NotNan<F>>, size: usize) -> Vec<NotNan<F>> {
+    const MAX_ITERATIONS: usize = 50;
+
+    values.sort();
+    let mut distinct = values.clone();
+    distinct.dedup();
+    if distinct.len() <= size {
+        return distinct;
+    }
+
+    let mut centers: Vec<NotNan<F>> = (0..size)
+        .map(|i| values[(2 * i + 1) * values.len() / (2 * size)])
+        .collect();
+    centers.dedup();
+
+    for _ in 0..MAX_ITERATIONS {
+        let mut sums = vec![0.0_f64; centers.len()];
+        let mut counts = vec![0_usize; centers.len()];
+        for &v in &values {
+            let code = nearest(&centers, v);
+            sums[code] += v.into_inner().into();
+            counts[code] += 1;
+        }
+        let mut next: Vec<NotNan<F>> = sums
+            .iter()
+            .zip(&counts)
+            .filter(|&(_, &count)| count > 0)
+            .map(|(&sum, &count)| NotNan::new(F::from_f64(sum / count as f64)).unwrap())
+            .collect();
+        next.dedup();
+        if next == centers {
+            break;
+        }
+        centers = next;
+    }
+    centers
+}
+
+/// Index of the value in the sorted `codebook` closest to `v`.
+fn nearest<F: Float>(codebook: &[NotNan<F>], v: NotNan<F>) -> usize {
+    let i = codebook.partition_point(|&c| c < v);
+    if i == 0 {
+        0
+    } else if i == codebook.len() || v - codebook[i - 1] <= codebook[i] - v {
+        i - 1
+    } else {
+        i
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{
+        Forest, NATIVE_FORMAT_VERSION, TreeNode,
+        parser::{read_lightgbm_model, read_xgboost_model, test_utils::read_features},
+    };
+
+    fn assert_within_bound<B: BinIndex>(
+        model: &MultiOutputForest,
+        quantized: &QuantizedForest<B>,
+        rows: &[Vec<f64>],
+    ) {
+        for x in rows {
+            for ((expected, actual), bound) in model
+                .predict(x)
+                .iter()
+                .zip(quantized.predict(x))
+                .zip(quantized.max_errors())
+            {
+                assert!((expected - actual).abs() <= bound + 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_lossless_quantization() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/xgboost/multiclass_classification");
+        let model = read_xgboost_model(data_dir.join("model.json")).unwrap();
+        let rows = read_features(&data_dir.join("X.csv"));
+
+        // enough codes for every distinct leaf value
+        let quantized = QuantizedForest::<u16>::new(&model, 4096).unwrap();
+        assert!(quantized.max_errors().iter().all(|&e| e == 0.0));
+        for x in &rows {
+            assert_eq!(quantized.predict(x), model.predict(x));
+        }
+    }
+
+    #[test]
+    fn test_lossy_quantization() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        for (path, data_dir) in [
+            (
+                "test_data/xgboost/regression/model.json",
+                "test_data/xgboost/regression",
+            ),
+            (
+                "test_data/xgboost/binary_classification/model.json",
+                "test_data/xgboost/binary_classification",
+            ),
+        ] {
+            let model = read_xgboost_model(root.join(path)).unwrap();
+            let rows = read_features(&root.join(data_dir).join("X.csv"));
+            for codebook_size in [4, 16, 64] {
+                let quantized = QuantizedForest::<u8>::new(&model, codebook_size).unwrap();
+                assert!(quantized.codebook.len() <= codebook_size);
+                if codebook_size == 4 {
+                    assert!(quantized.max_errors().iter().all(|&e| e > 0.0));
+                }
+                assert_within_bound(&model, &quantized, &rows);
+            }
+        }
+    }
+
+    #[test]
+    fn test_missing_values() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/lightgbm/binary_classification");
+        let model = read_lightgbm_model(data_dir.join("model.txt")).unwrap();
+        let quantized = QuantizedForest::<u16>::new(&model, 65535).unwrap();
+        for (i, mut x) in read_features(&data_dir.join("X.csv"))
+            .into_iter()
+            .enumerate()
+        {
+            x[i % 5] = f64::NAN;
+            assert_eq!(quantized.predict(&x), model.predict(&x));
+        }
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/xgboost/regression");
+        let model = read_xgboost_model(data_dir.join("model.json")).unwrap();
+        let quantized = QuantizedForest::<u8>::new(&model, 32).unwrap();
+
+        let json = serde_json::to_string(&quantized).unwrap();
+        assert!(json.starts_with(&format!(r#"{{"version":{NATIVE_FORMAT_VERSION},"#)));
+        let loaded: QuantizedForest<u8> = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.max_errors(), quantized.max_errors());
+        for x in read_features(&data_dir.join("X.csv")) {
+            assert_eq!(loaded.predict(&x), quantized.predict(&x));
+        }
+
+        // files written before the version tag are version 1
+        let mut value = serde_json::to_value(&quantized).unwrap();
+        value.as_object_mut().unwrap().remove("version");
+        assert!(serde_json::from_value::<QuantizedForest<u8>>(value.clone()).is_ok());
+        value["version"] = (NATIVE_FORMAT_VERSION + 1).into();
+        let err = serde_json::from_value::<QuantizedForest<u8>>(value).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("unsupported native format version")
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let model =
+            read_lightgbm_model(root.join("test_data/lightgbm/regression/model.txt")).unwrap();
+        assert!(matches!(
+            QuantizedForest::<u8>::new(&model, 0),
+            Err(QuantizeError::InvalidCodebookSize { .. })
+        ));
+        assert!(matches!(
+            QuantizedForest::<u8>::new(&model, 256),
+            Err(QuantizeError::InvalidCodebookSize { .. })
+        ));
+
+        // 300 stumps splitting feature 0 at distinct thresholds
+        let stump = |threshold: f64| {
+            Tree::from_nodes(vec![
+                TreeNode::split(0, 0, threshold, 1, 2),
+                TreeNode::leaf(1, 1.0),
+                TreeNode::leaf(2, 2.0),
+            ])
+        };
+        let trees = (0..300).map(|i| stump(i as f64)).collect();
+        let model = MultiOutputForest::new(vec![Forest::new(0.0, trees)]);
+        assert!(matches!(
+            QuantizedForest::<u8>::new(&model, 16),
+            Err(QuantizeError::TooManyThresholds {
+                feature: 0,
+                count: 300,
+                max: 254
+            })
+        ));
+        assert!(QuantizedForest::<u16>::new(&model, 16).is_ok());
+    }
+
+    #[test]
+    fn test_nearest() {
+        let codebook: Vec<NotNan<f64>> = [0.0, 1.0, 3.0]
+            .into_iter()
+            .map(|v| NotNan::new(v).unwrap())
+            .collect();
+        let code = |v| nearest(&codebook, NotNan::new(v).unwrap());
+        assert_eq!(code(-1.0), 0);
+        assert_eq!(code(0.4), 0);
+        assert_eq!(code(0.6), 1);
+        assert_eq!(code(2.1), 2);
+        assert_eq!(code(5.0), 2);
+    }
+}
@@ -0,0 +1,405 @@
+//! Bounds on the output of a model over boxes of inputs, found by propagating per-feature
+//! intervals through the splits of each tree.
+//!
+//! Within a tree the bounds are tight: a leaf counts only if some input of the box reaches it.
+//! Across trees they are sound but may be loose, since trees are bounded independently of each
+//! other.
+
+use crate::{
+    Error, Float, Forest, MultiOutputForest, Tree, error::malformed_tree, float::summation_error,
+};
+
+/// Values a feature can take: the closed interval `[min, max]`, plus NaN when `missing` is set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeatureDomain<F: Float = f64> {
+    pub min: F,
+    pub max: F,
+    pub missing: bool,
+}
+
+impl<F: Float> FeatureDomain<F> {
+    /// Any value, including infinities and NaN.
+    pub fn unbounded() -> Self {
+        Self {
+            min: F::neg_infinity(),
+            max: F::infinity(),
+            missing: true,
+        }
+    }
+
+    /// Values from `min` to `max`, both included, never missing.
+    pub fn new(min: F, max: F) -> Self {
+        Self {
+            min,
+            max,
+            missing: false,
+        }
+    }
+}
+
+/// Output bounds of a single tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeRange<F: Float = f64> {
+    /// Smallest leaf value reachable from the box.
+    pub min: F,
+    /// Largest leaf value reachable from the box.
+    pub max: F,
+    /// Ids of the nodes no input of the box reaches, in increasing order. With unbounded domains
+    /// these are the nodes whose path conditions contradict each other.
+    pub unreachable_nodes: Vec<usize>,
+}
+
+/// Output bounds of a forest, including its base value.
+///
+/// The bounds are `f64` whatever the precision of the model, and widened by the rounding error
+/// of summing the trees, so that every prediction over the box lies within them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForestRange<F: Float = f64> {
+    pub min: f64,
+    pub max: f64,
+    /// Bounds of each tree, in order.
+    pub trees: Vec<TreeRange<F>>,
+}
+
+/// Values still possible for a feature on the path to a node. The lower bound is always
+/// inclusive, the upper bound exclusive once a split has cut it.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Interval<F> {
+    pub(crate) lo: F,
+    pub(crate) hi: F,
+    hi_inclusive: bool,
+    /// Whether some non-NaN value is possible.
+    present: bool,
+    missing: bool,
+}
+
+impl<F: Float> Interval<F> {
+    pub(crate) fn new(domain: &FeatureDomain<F>) -> Self {
+        Self {
+            lo: domain.min,
+            hi: domain.max,
+            hi_inclusive: true,
+            present: true,
+            missing: domain.missing,
+        }
+    }
+
+    /// Values going left at a split on `threshold`, i.e. below it or missing when the default
+    /// direction is left.
+    pub(crate) fn left(self, threshold: F, default_left: Option<bool>) -> Self {
+        let (hi, hi_inclusive) = if self.hi < threshold {
+            (self.hi, self.hi_inclusive)
+        } else {
+            (threshold, false)
+        };
+        Self {
+            hi,
+            hi_inclusive,
+            present: self.present && self.lo < threshold,
+            missing: self.missing && default_left == Some(true),
+            ..self
+        }
+    }
+
+    /// Values going right at a split on `threshold`, i.e. at least it or missing when the
+    /// default direction is right.
+    pub(crate) fn right(self, threshold: F, default_left: Option<bool>) -> Self {
+        Self {
+            lo: self.lo.max(threshold),
+            present: self.present
+                && (self.hi > threshold || (self.hi == threshold && self.hi_inclusive)),
+            missing: self.missing && default_left == Some(false),
+            ..self
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        !self.present && !self.missing
+    }
+}
+
+/// Per-feature intervals, features past the given domains being unbounded.
+fn intervals<F: Float>(
+    domains: &[FeatureDomain<F>],
+    n_features: usize,
+) -> Result<Vec<Interval<F>>, Error> {
+    if let Some(feature) = domains
+        .iter()
+        .position(|domain| domain.min.is_nan() || domain.max.is_nan() || domain.min > domain.max)
+    {
+        return Err(Error::InvalidDomain { feature });
+    }
+    Ok((0..n_features.max(domains.len()))
+        .map(|i| Interval::new(domains.get(i).unwrap_or(&FeatureDomain::unbounded())))
+        .collect())
+}
+
+/// Depth-first search of the nodes reachable from a box.
+struct Reach<'a, F: Float> {
+    tree: &'a Tree<F>,
+    intervals: Vec<Interval<F>>,
+    /// Whether each node, by position in the node map, is reachable.
+    reached: Vec<bool>,
+    range: Option<(F, F)>,
+}
+
+impl<F: Float> Reach<'_, F> {
+    fn visit(&mut self, id: usize, depth: usize) -> Result<(), Error> {
+        // a valid path visits every node at most once
+        if depth >= self.tree.node_map.len() {
+            return Err(malformed_tree("cycle in tree"));
+        }
+        let (position, _, node) = self
+            .tree
+            .node_map
+            .get_full(&id)
+            .ok_or_else(|| malformed_tree(format!("node {id} does not exist")))?;
+        self.reached[position] = true;
+        if node.is_leaf() {
+            let value = node.value.into_inner();
+            self.range = Some(match self.range {
+                Some((min, max)) => (min.min(value), max.max(value)),
+                None => (value, value),
+            });
+            return Ok(());
+        }
+        let (Some(left), Some(right)) = (node.left, node.right) else {
+            return Err(malformed_tree(format!("node {id} has a single child")));
+        };
+
+        let feature = node.split_index;
+        let threshold = node.split_condition.into_inner();
+        let interval = self.intervals[feature];
+        for (child, restricted) in [
+            (left, interval.left(threshold, node.default_left)),
+            (right, interval.right(threshold, node.default_left)),
+        ] {
+            if !restricted.is_empty() {
+                self.intervals[feature] = restricted;
+                self.visit(child, depth + 1)?;
+            }
+        }
+        self.intervals[feature] = interval;
+        Ok(())
+    }
+}
+
+impl<F: Float> Tree<F> {
+    /// Smallest and largest prediction over the inputs whose features lie in `domains`, and the
+    /// nodes none of them reach. Features without a domain are unbounded.
+    ///
+    /// Missing values at a split without default direction make [`Tree::predict`] panic, so
+    /// they are not followed. Fails with [`Error::InvalidDomain`] on a domain with NaN bounds or
+    /// `min > max`.
+    pub fn output_range(&self, domains: &[FeatureDomain<F>]) -> Result<TreeRange<F>, Error> {
+        let mut reach = Reach {
+            tree: self,
+            intervals: intervals(domains, self.n_features())?,
+            reached: vec![false; self.node_map.len()],
+            range: None,
+        };
+        reach.visit(self.root, 0)?;
+        // every non-empty box reaches a leaf of a well-formed tree
+        let (min, max) = reach
+            .range
+            .ok_or_else(|| malformed_tree("no leaf is reachable"))?;
+        let mut unreachable_nodes: Vec<usize> = self
+            .node_map
+            .keys()
+            .zip(&reach.reached)
+            .filter(|&(_, &reached)| !reached)
+            .map(|(&id, _)| id)
+            .collect();
+        unreachable_nodes.sort_unstable();
+        Ok(TreeRange {
+            min,
+            max,
+            unreachable_nodes,
+        })
+    }
+}
+
+impl<F: Float> Forest<F> {
+    /// Bounds on [`Forest::predict`] over the inputs whose features lie in `domains`, summing
+    /// the bounds of every tree, see [`Tree::output_range`].
+    pub fn output_range(&self, domains: &[FeatureDomain<F>]) -> Result<ForestRange<F>, Error> {
+        let trees = self
+            .trees
+            .iter()
+            .map(|tree| tree.output_range(domains))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let base_value: f64 = self.base_value.into();
+        let (mut min, mut max, mut magnitude) = (base_value, base_value, base_value.abs());
+        for tree in &trees {
+            let (tree_min, tree_max): (f64, f64) = (tree.min.into(), tree.max.into());
+            min += tree_min;
+            max += tree_max;
+            magnitude += tree_min.abs().max(tree_max.abs());
+        }
+        // covers the rounding of both the prediction and the sums above
+        let slack = 2.0 * summation_error::<F>(magnitude, self.trees.len() + 1);
+        Ok(ForestRange {
+            min: min - slack,
+            max: max + slack,
+            trees,
+        })
+    }
+}
+
+impl<F: Float> MultiOutputForest<F> {
+    /// Bounds on every output over the inputs whose features lie in `domains`, see
+    /// [`Forest::output_range`].
+    pub fn output_range(&self, domains: &[FeatureDomain<F>]) -> Result<Vec<ForestRange<F>>, Error> {
+        self.forests
+            .iter()
+            .map(|forest| forest.output_range(domains))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{
+        LeafNumbering, TreeNode,
+        parser::{read_lightgbm_model, read_xgboost_model, test_utils::read_features},
+    };
+
+    /// x0 < 5, then x0 < 7 on the left, whose right child needs x0 in [7, 5) or a missing
+    /// value routed right, which the default directions rule out.
+    fn contradictory_tree() -> Tree {
+        Tree::from_nodes(vec![
+            TreeNode::split(0, 0, 5.0, 1, 2).with_default_left(true),
+            TreeNode::split(1, 0, 7.0, 3, 4).with_default_left(true),
+            TreeNode::leaf(2, 3.0),
+            TreeNode::leaf(3, 1.0),
+            TreeNode::leaf(4, 100.0),
+        ])
+    }
+
+    #[test]
+    fn test_unreachable_nodes() {
+        let tree = contradictory_tree();
+        let range = tree.output_range(&[]).unwrap();
+        assert_eq!(range.unreachable_nodes, vec![4]);
+        assert_eq!((range.min, range.max), (1.0, 3.0));
+
+        // values from 6 on never go left at the root
+        let range = tree.output_range(&[FeatureDomain::new(6.0, 10.0)]).unwrap();
+        assert_eq!(range.unreachable_nodes, vec![1, 3, 4]);
+        assert_eq!((range.min, range.max), (3.0, 3.0));
+
+        // the split is strict, so the upper bound 5 itself goes right
+        let range = tree.output_range(&[FeatureDomain::new(0.0, 5.0)]).unwrap();
+        assert_eq!(range.unreachable_nodes, vec![4]);
+        assert_eq!((range.min, range.max), (1.0, 3.0));
+        let range = tree.output_range(&[FeatureDomain::new(0.0, 4.0)]).unwrap();
+        assert_eq!((range.min, range.max), (1.0, 1.0));
+    }
+
+    #[test]
+    fn test_invalid_domain() {
+        let tree = contradictory_tree();
+        for domain in [
+            FeatureDomain::new(1.0, 0.0),
+            FeatureDomain::new(f64::NAN, 0.0),
+        ] {
+            assert!(matches!(
+                tree.output_range(&[FeatureDomain::unbounded(), domain]),
+                Err(Error::InvalidDomain { feature: 1 })
+            ));
+        }
+    }
+
+    fn check_model(model: &MultiOutputForest, rows: &[Vec<f64>]) {
+        let n_features = rows[0].len();
+        let domains: Vec<FeatureDomain> = (0..n_features)
+            .map(|i| {
+                let column = rows.iter().map(|x| x[i]).filter(|v| !v.is_nan());
+                FeatureDomain {
+                    min: column.clone().fold(f64::INFINITY, f64::min),
+                    max: column.fold(f64::NEG_INFINITY, f64::max),
+                    missing: rows.iter().any(|x| x[i].is_nan()),
+                }
+            })
+            .collect();
+
+        let ranges = model.output_range(&domains).unwrap();
+        let unbounded = model.output_range(&[]).unwrap();
+        for ((forest, range), unbounded) in model.forests.iter().zip(&ranges).zip(&unbounded) {
+            assert!(unbounded.min <= range.min && range.max <= unbounded.max);
+            for (tree, tree_range) in forest.trees.iter().zip(&unbounded.trees) {
+                let (min, max) = tree.leaf_value_range();
+                assert!(min <= tree_range.min && tree_range.max <= max);
+            }
+            for x in rows {
+                let prediction = forest.predict(x).into_inner();
+                assert!(range.min <= prediction && prediction <= range.max);
+                for (tree, tree_range) in forest.trees.iter().zip(&range.trees) {
+                    let prediction = tree.predict(x).into_inner();
+                    assert!(tree_range.min <= prediction && prediction <= tree_range.max);
+                    assert!(
+                        !tree_range
+                            .unreachable_nodes
+                            .contains(&tree.predict_leaf(x, LeafNumbering::NodeId))
+                    );
+                }
+
+                // a single point reaches a single leaf per tree
+                let point: Vec<FeatureDomain> = x
+                    .iter()
+                    .map(|&v| {
+                        if v.is_nan() {
+                            FeatureDomain {
+                                min: 0.0,
+                                max: 0.0,
+                                missing: true,
+                            }
+                        } else {
+                            FeatureDomain::new(v, v)
+                        }
+                    })
+                    .collect();
+                let point = forest.output_range(&point).unwrap();
+                for (tree, tree_range) in forest.trees.iter().zip(&point.trees) {
+                    if x.iter().all(|v| !v.is_nan()) {
+                        assert_eq!(tree_range.min, tree.predict(x).into_inner());
+                        assert_eq!(tree_range.max, tree_range.min);
+                    }
+                }
+                assert!(point.min <= prediction && prediction <= point.max);
+            }
+        }
+    }
+
+    #[test]
+    fn test_output_range_xgboost() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        for dataset in [
+            "regression",
+            "binary_classification",
+            "multiclass_classification",
+        ] {
+            let data_dir = root.join("test_data/xgboost").join(dataset);
+            let model = read_xgboost_model(data_dir.join("model.json")).unwrap();
+            check_model(&model, &read_features(&data_dir.join("X.csv")));
+        }
+    }
+
+    #[test]
+    fn test_output_range_lightgbm() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        for dataset in [
+            "regression",
+            "binary_classification",
+            "multiclass_classification",
+        ] {
+            let data_dir = root.join("test_data/lightgbm").join(dataset);
+            let model = read_lightgbm_model(data_dir.join("model.txt")).unwrap();
+            check_model(&model, &read_features(&data_dir.join("X.csv")));
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! Predictions over sparse rows, following the LibSVM convention that absent entries are missing.
+
+use std::{
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use ordered_float::NotNan;
+use thiserror::Error;
+
+use crate::{Error, Float, Forest, MultiOutputForest, Tree};
+
+/// Custom error types for sparse input
+#[derive(Debug, Error)]
+pub enum SparseError {
+    #[error("IO error: {source}")]
+    Io {
+        #[from]
+        source: std::io::Error,
+    },
+    #[error("Invalid sparse row: {message}")]
+    InvalidRow { message: String },
+    #[error("Parse error at line {line}: {message}")]
+    Parse { line: usize, message: String },
+}
+
+fn invalid_row(message: impl Into<String>) -> SparseError {
+    SparseError::InvalidRow {
+        message: message.into(),
+    }
+}
+
+/// A row given as `(index, value)` pairs with strictly increasing indices.
+#[derive(Debug, Clone, Copy)]
+pub struct SparseRow<'a, F: Float = f64> {
+    indices: &'a [usize],
+    values: &'a [F],
+}
+
+impl<'a, F: Float> SparseRow<'a, F> {
+    pub fn new(indices: &'a [usize], values: &'a [F]) -> Result<Self, SparseError> {
+        if indices.len() != values.len() {
+            return Err(invalid_row(format!(
+                "{} indices for {} values",
+                indices.len(),
+                values.len()
+            )));
+        }
+        if indices.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(invalid_row("indices are not strictly increasing"));
+        }
+        Ok(Self { indices, values })
+    }
+
+    /// Value of feature `index`, NaN when absent.
+    pub fn get(&self, index: usize) -> F {
+        self.indices
+            .binary_search(&index)
+            .map_or_else(|_| F::nan(), |i| self.values[i])
+    }
+
+    pub fn nnz(&self) -> usize {
+        self.indices.len()
+    }
+}
+
+/// Rows in compressed sparse row layout: the entries of row `i` are
+/// `indices[indptr[i]..indptr[i + 1]]` and `values[indptr[i]..indptr[i + 1]]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsrMatrix<F: Float = f64> {
+    indptr: Vec<usize>,
+    indices: Vec<usize>,
+    values: Vec<F>,
+}
+
+impl<F: Float> CsrMatrix<F> {
+    pub fn new(
+        indptr: Vec<usize>,
+        indices: Vec<usize>,
+        values: Vec<F>,
+    ) -> Result<Self, SparseError> {
+        if indices.len() != values.len() {
+            return Err(invalid_row(format!(
+                "{} indices for {} values",
+                indices.len(),
+                values.len()
+            )));
+        }
+        let valid_indptr = indptr.first() == Some(&0)
+            && indptr.last() == Some(&indices.len())
+            && indptr.windows(2).all(|w| w[0] <= w[1]);
+        if !valid_indptr {
+            return Err(invalid_row("invalid row pointers"));
+        }
+        let matrix = Self {
+            indptr,
+            indices,
+            values,
+        };
+        for i in 0..matrix.n_rows() {
+            let range = matrix.indptr[i]..matrix.indptr[i + 1];
+            SparseRow::new(&matrix.indices[range.clone()], &matrix.values[range])?;
+        }
+        Ok(matrix)
+    }
+
+    pub fn n_rows(&self) -> usize {
+        self.indptr.len() - 1
+    }
+
+    pub fn row(&self, i: usize) -> SparseRow<'_, F> {
+        let range = self.indptr[i]..self.indptr[i + 1];
+        SparseRow {
+            indices: &self.indices[range.clone()],
+            values: &self.values[range],
+        }
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = SparseRow<'_, F>> {
+        (0..self.n_rows()).map(|i| self.row(i))
+    }
+}
+
+impl<F: Float> Tree<F> {
+    pub fn predict_sparse(&self, row: SparseRow<F>) -> NotNan<F> {
+        self.predict_by(|index| row.get(index))
+    }
+
+    /// Fallible [`Self::predict_sparse`], see [`Tree::try_predict`]: an absent entry at a split
+    /// without default direction is an [`Error::MissingValue`].
+    pub fn try_predict_sparse(&self, row: SparseRow<F>) -> Result<NotNan<F>, Error> {
+        self.try_predict_by(|node| Ok(row.get(node.split_index)))
+    }
+}
+
+impl<F: Float> Forest<F> {
+    pub fn predict_sparse(&self, row: SparseRow<F>) -> NotNan<F> {
+        self.predict_by(|index| row.get(index))
+    }
+
+    /// Fallible [`Self::predict_sparse`], see [`Tree::try_predict_sparse`].
+    pub fn try_predict_sparse(&self, row: SparseRow<F>) -> Result<NotNan<F>, Error> {
+        self.try_predict_by(|node| Ok(row.get(node.split_index)))
+    }
+}
+
+impl<F: Float> MultiOutputForest<F> {
+    pub fn predict_sparse(&self, row: SparseRow<F>) -> Vec<NotNan<F>> {
+        self.forests
+            .iter()
+            .map(|forest| forest.predict_sparse(row))
+            .collect()
+    }
+
+    /// Fallible [`Self::predict_sparse`], see [`Tree::try_predict_sparse`].
+    pub fn try_predict_sparse(&self, row: SparseRow<F>) -> Result<Vec<NotNan<F>>, Error> {
+        self.forests
+            .iter()
+            .map(|forest| forest.try_predict_sparse(row))
+            .collect()
+    }
+
+    /// Predict every row of `matrix`, one vector of outputs per row, failing on the first row
+    /// [`Self::try_predict_sparse`] fails on.
+    pub fn predict_csr(&self, matrix: &CsrMatrix<F>) -> Result<Vec<Vec<NotNan<F>>>, Error> {
+        matrix
+            .rows()
+            .map(|row| self.try_predict_sparse(row))
+            .collect()
+    }
+}
+
+/// Read a LibSVM text file into its labels and a CSR matrix of features.
+///
+/// Lines are `label index:value ...` with 0-based feature indices, as XGBoost reads them.
+/// `qid:` entries and `#` comments are ignored, and entries of a line may come in any order.
+pub fn read_libsvm<F: Float>(
+    path: impl AsRef<Path>,
+) -> Result<(Vec<f64>, CsrMatrix<F>), SparseError> {
+    let reader = BufReader::new(std::fs::File::open(path)?);
+    let mut labels = Vec::new();
+    let mut indptr = vec![0];
+    let mut indices = Vec::new();
+    let mut values = Vec::new();
+
+    for (line_idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        let parse_error = |message: String| SparseError::Parse {
+            line: line_idx + 1,
+            message,
+        };
+        let content = line.split('#').next().unwrap_or_default();
+        let mut tokens = content.split_whitespace();
+        let Some(label) = tokens.next() else {
+            continue;
+        };
+        labels.push(
+            label
+                .parse()
+                .map_err(|_| parse_error(format!("invalid label {label}")))?,
+        );
+
+        let mut entries = Vec::new();
+        for token in tokens {
+            let (index, value) = token
+                .split_once(':')
+                .ok_or_else(|| parse_error(format!("invalid entry {token}")))?;
+            if index == "qid" {
+                continue;
+            }
+            let index: usize = index
+                .parse()
+                .map_err(|_| parse_error(format!("invalid index {index}")))?;
+            let value: f64 = value
+                .parse()
+                .map_err(|_| parse_error(format!("invalid value {value}")))?;
+            entries.push((index, F::from_f64(value)));
+        }
+        entries.sort_by_key(|&(index, _)| index);
+        if entries.windows(2).any(|w| w[0].0 == w[1].0) {
+            return Err(parse_error("duplicate feature index".to_string()));
+        }
+        indices.extend(entries.iter().map(|&(index, _)| index));
+        values.extend(entries.iter().map(|&(_, value)| value));
+        indptr.push(indices.len());
+    }
+
+    Ok((labels, CsrMatrix::new(indptr, indices, values)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fmt::Write, path::PathBuf};
+
+    use super::*;
+    use crate::{
+        TreeNode,
+        parser::{read_lightgbm_model, test_utils::read_features},
+    };
+
+    #[test]
+    fn test_predict_sparse() {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = root.join("test_data/lightgbm/binary_classification");
+        let model = read_lightgbm_model(data_dir.join("model.txt")).unwrap();
+
+        // drop every third value, which the dense row marks as missing
+        let mut dense_rows = Vec::new();
+        let mut libsvm = String::new();
+        for (r, mut x) in read_features(&data_dir.join("X.csv"))
+            .into_iter()
+            .enumerate()
+        {
+            write!(libsvm, "{}", r % 2).unwrap();
+            for (i, v) in x.iter_mut().enumerate().rev() {
+                if (r + i) % 3 == 0 {
+                    *v = f64::NAN;
+                } else {
+                    write!(libsvm, " {i}:{v:?}").unwrap();
+                }
+            }
+            writeln!(libsvm, " # row {r}").unwrap();
+            dense_rows.push(x);
+        }
+
+        let path = std::env::temp_dir().join(format!("silva-{}.libsvm", std::process::id()));
+        std::fs::write(&path, libsvm).unwrap();
+        let (labels, matrix) = read_libsvm::<f64>(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(labels.len(), dense_rows.len());
+        assert_eq!(matrix.n_rows(), dense_rows.len());
+        let predictions = model.predict_csr(&matrix).unwrap();
+        for ((x, row), prediction) in dense_rows.iter().zip(matrix.rows()).zip(&predictions) {
+            assert_eq!(prediction, &model.predict(x));
+            let forest = &model.forests[0];
+            assert_eq!(forest.predict_sparse(row), forest.predict(x));
+            assert_eq!(forest.try_predict_sparse(row).unwrap(), forest.predict(x));
+            assert_eq!(
+                forest.trees[0].predict_sparse(row),
+                forest.trees[0].predict(x)
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert!(SparseRow::new(&[0, 2], &[1.0]).is_err());
+        assert!(SparseRow::new(&[2, 0], &[1.0, 2.0]).is_err());
+        assert!(CsrMatrix::new(vec![0, 2, 1], vec![0, 1], vec![1.0, 2.0]).is_err());
+        assert!(matches!(
+            CsrMatrix::new(vec![0, 1, 2], vec![0, 1], vec![1.0]),
+            Err(SparseError::InvalidRow { .. })
+        ));
+        assert!(CsrMatrix::new(vec![0, 1, 1], vec![0], vec![1.0, 2.0]).is_err());
+
+        let row = SparseRow::<f64>::new(&[1, 4], &[1.0, 2.0]).unwrap();
+        assert_eq!(row.get(4), 2.0);
+        assert!(row.get(2).is_nan());
+    }
+
+    #[test]
+    fn test_absent_without_default_direction() {
+        let tree = Tree::from_nodes(vec![
+            TreeNode::split(0, 1, 1.0, 1, 2),
+            TreeNode::leaf(1, 1.0),
+            TreeNode::leaf(2, 2.0),
+        ]);
+        let model = MultiOutputForest::new(vec![Forest::new(0.0, vec![tree])]);
+        let matrix = CsrMatrix::new(vec![0, 1, 2], vec![1, 0], vec![3.0, 3.0]).unwrap();
+        assert_eq!(model.try_predict_sparse(matrix.row(0)).unwrap(), [2.0]);
+        assert!(matches!(
+            model.predict_csr(&matrix),
+            Err(Error::MissingValue {
+                feature: 1,
+                node: 0
+            })
+        ));
+    }
+}
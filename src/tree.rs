@@ -1,67 +1,384 @@
+use std::{cmp::Ordering, sync::OnceLock};
+
 use ordered_float::NotNan;
+use rustc_hash::FxHashMap;
 use serde::{Deserialize, Serialize};
 
-use crate::map::FxIndexMap;
+use crate::{
+    error::{Error, malformed_tree},
+    float::Float,
+    map::FxIndexMap,
+    metadata::ModelMetadata,
+};
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
-pub struct TreeNode {
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+// `Float` already implies the serde traits
+#[serde(bound = "")]
+pub struct TreeNode<F: Float = f64> {
     pub(crate) id: usize,
     #[serde(rename(serialize = "si", deserialize = "si"))]
     pub(crate) split_index: usize,
     #[serde(rename(serialize = "sc", deserialize = "sc"))]
-    pub(crate) split_condition: NotNan<f64>,
+    pub(crate) split_condition: NotNan<F>,
     #[serde(rename(serialize = "l", deserialize = "l"))]
     pub(crate) left: Option<usize>,
     #[serde(rename(serialize = "r", deserialize = "r"))]
     pub(crate) right: Option<usize>,
     #[serde(rename(serialize = "v", deserialize = "v"))]
-    pub(crate) value: NotNan<f64>,
+    pub(crate) value: NotNan<F>,
+    /// Direction taken by missing (NaN) feature values, `None` if the model does not support them.
+    #[serde(
+        rename(serialize = "dl", deserialize = "dl"),
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub(crate) default_left: Option<bool>,
+    /// Training cover of the node (sum of hessians or sample count), used to weight branches by
+    /// how much data went through them.
+    #[serde(
+        rename(serialize = "c", deserialize = "c"),
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub(crate) cover: Option<NotNan<F>>,
+    /// Expected output of a split node, `None` for leaves and when the model does not record it.
+    #[serde(
+        rename(serialize = "iv", deserialize = "iv"),
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub(crate) internal_value: Option<NotNan<F>>,
+    /// Loss reduction of the split, `None` for leaves and when the model does not record it.
+    #[serde(
+        rename(serialize = "g", deserialize = "g"),
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub(crate) gain: Option<NotNan<F>>,
+    /// Sum of the hessians of the training rows that reached the node, when the model records it
+    /// apart from the cover. XGBoost models leave it out since their cover is that sum.
+    #[serde(
+        rename(serialize = "h", deserialize = "h"),
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub(crate) hessian: Option<NotNan<F>>,
 }
 
-impl TreeNode {
+impl<F: Float> TreeNode<F> {
     pub fn is_leaf(&self) -> bool {
         self.left.is_none() && self.right.is_none()
     }
 
-    pub fn get_value(&self) -> NotNan<f64> {
+    pub fn get_value(&self) -> NotNan<F> {
         self.value
     }
+
+    pub fn cover(&self) -> Option<NotNan<F>> {
+        self.cover
+    }
+
+    pub fn internal_value(&self) -> Option<NotNan<F>> {
+        self.internal_value
+    }
+
+    pub fn gain(&self) -> Option<NotNan<F>> {
+        self.gain
+    }
+
+    pub fn hessian(&self) -> Option<NotNan<F>> {
+        self.hessian
+    }
+
+    /// Id of the child to visit for `feature`, routing NaN to the default direction.
+    pub(crate) fn next_node_id(&self, feature: F) -> Option<usize> {
+        let go_left = match NotNan::new(feature) {
+            Ok(feature) => feature < self.split_condition,
+            Err(_) => self
+                .default_left
+                .expect("missing value at a node without a default direction"),
+        };
+        if go_left { self.left } else { self.right }
+    }
+
+    /// Value of the split feature in `x`, failing when `x` is too short for it.
+    pub(crate) fn try_feature(&self, x: &[F]) -> Result<F, Error> {
+        x.get(self.split_index).copied().ok_or(Error::FeatureIndex {
+            feature: self.split_index,
+            node: self.id,
+            actual: x.len(),
+        })
+    }
+
+    /// Fallible [`Self::next_node_id`], failing on a missing value without default direction
+    /// and on a node with a single child.
+    pub(crate) fn try_next_node_id(&self, feature: F) -> Result<usize, Error> {
+        let go_left = match NotNan::new(feature) {
+            Ok(feature) => feature < self.split_condition,
+            Err(_) => self.default_left.ok_or(Error::MissingValue {
+                feature: self.split_index,
+                node: self.id,
+            })?,
+        };
+        let next = if go_left { self.left } else { self.right };
+        next.ok_or_else(|| malformed_tree(format!("node {} has a single child", self.id)))
+    }
+
+    /// Convert the node to another floating point precision.
+    pub fn cast<G: Float>(&self) -> TreeNode<G> {
+        TreeNode {
+            id: self.id,
+            split_index: self.split_index,
+            split_condition: cast_not_nan(self.split_condition),
+            left: self.left,
+            right: self.right,
+            value: cast_not_nan(self.value),
+            default_left: self.default_left,
+            cover: self.cover.map(cast_not_nan),
+            internal_value: self.internal_value.map(cast_not_nan),
+            gain: self.gain.map(cast_not_nan),
+            hessian: self.hessian.map(cast_not_nan),
+        }
+    }
+
+    fn sort_key(&self) -> impl Ord {
+        (
+            self.id,
+            self.split_index,
+            self.split_condition,
+            self.left,
+            self.right,
+            self.value,
+            self.default_left,
+            self.cover,
+            self.internal_value,
+            self.gain,
+            self.hessian,
+        )
+    }
+}
+
+// `NotNan` is totally ordered even though `F` itself is not, which derives cannot express
+impl<F: Float> Eq for TreeNode<F> {}
+
+impl<F: Float> PartialOrd for TreeNode<F> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<F: Float> Ord for TreeNode<F> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sort_key().cmp(&other.sort_key())
+    }
+}
+
+pub(crate) fn cast_not_nan<F: Float, G: Float>(v: NotNan<F>) -> NotNan<G> {
+    // narrowing may overflow to infinity, but never produces NaN
+    NotNan::new(G::from_f64(v.into_inner().into())).expect("cast of a non-NaN value")
+}
+
+/// Numbering of the leaves returned by `predict_leaf`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeafNumbering {
+    /// Node id of the leaf, the numbering of XGBoost.
+    NodeId,
+    /// Position of the leaf among the leaves of its tree ordered by node id, the numbering of
+    /// LightGBM.
+    LeafIndex,
+}
+
+impl LeafNumbering {
+    /// Numbering of the library the model was converted from, `None` when the metadata does not
+    /// name XGBoost or LightGBM as the source.
+    pub fn of(metadata: &ModelMetadata) -> Option<Self> {
+        match metadata.source.as_ref()?.library.as_str() {
+            "xgboost" => Some(Self::NodeId),
+            "lightgbm" => Some(Self::LeafIndex),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Tree {
+#[serde(bound = "")]
+pub struct Tree<F: Float = f64> {
     #[serde(rename(serialize = "nm", deserialize = "nm"))]
-    node_map: FxIndexMap<usize, TreeNode>,
-    root: usize,
+    pub(crate) node_map: FxIndexMap<usize, TreeNode<F>>,
+    pub(crate) root: usize,
+    /// [`LeafNumbering::LeafIndex`] of every leaf by node id, computed on first use.
+    #[serde(skip)]
+    leaf_indices: OnceLock<FxHashMap<usize, usize>>,
 }
 
-impl Tree {
-    pub fn new(node_map: FxIndexMap<usize, TreeNode>, root: usize) -> Self {
-        Self { node_map, root }
+impl<F: Float> Tree<F> {
+    pub fn new(node_map: FxIndexMap<usize, TreeNode<F>>, root: usize) -> Self {
+        Self {
+            node_map,
+            root,
+            leaf_indices: OnceLock::new(),
+        }
     }
 
-    pub fn from_nodes(mut nodes: Vec<TreeNode>) -> Self {
+    pub fn from_nodes(mut nodes: Vec<TreeNode<F>>) -> Self {
         nodes.sort_by_key(|node| node.id);
         let root_id = nodes[0].id;
-        let node_map: FxIndexMap<usize, TreeNode> =
+        let node_map: FxIndexMap<usize, TreeNode<F>> =
             nodes.into_iter().map(|node| (node.id, node)).collect();
         Self::new(node_map, root_id)
     }
 
-    pub fn predict(&self, x: &[f64]) -> NotNan<f64> {
+    /// Convert the tree to another floating point precision.
+    pub fn cast<G: Float>(&self) -> Tree<G> {
+        Tree::new(
+            self.node_map
+                .iter()
+                .map(|(&id, node)| (id, node.cast()))
+                .collect(),
+            self.root,
+        )
+    }
+
+    pub fn predict(&self, x: &[F]) -> NotNan<F> {
+        self.predict_by(|index| x[index])
+    }
+
+    /// Smallest and largest leaf value, bounding every prediction of the tree.
+    pub fn leaf_value_range(&self) -> (F, F) {
+        let mut leaves = self
+            .node_map
+            .values()
+            .filter(|node| node.is_leaf())
+            .map(|node| node.value);
+        let first = leaves.next().expect("tree without leaves");
+        let (min, max) = leaves.fold((first, first), |(min, max), v| (min.min(v), max.max(v)));
+        (min.into_inner(), max.into_inner())
+    }
+
+    /// Number of features the splits refer to, i.e. the largest split index plus one.
+    pub fn n_features(&self) -> usize {
+        self.node_map
+            .values()
+            .filter(|node| !node.is_leaf())
+            .map(|node| node.split_index + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Fallible [`Self::predict`], checking feature indices, missing values and the tree
+    /// structure instead of panicking.
+    pub fn try_predict(&self, x: &[F]) -> Result<NotNan<F>, Error> {
+        self.try_predict_by(|node| node.try_feature(x))
+    }
+
+    /// Fallible [`Self::predict_by`], reading the feature of each split with `feature`.
+    pub(crate) fn try_predict_by(
+        &self,
+        feature: impl Fn(&TreeNode<F>) -> Result<F, Error>,
+    ) -> Result<NotNan<F>, Error> {
+        let node = |id: usize| {
+            self.node_map
+                .get(&id)
+                .ok_or_else(|| malformed_tree(format!("node {id} does not exist")))
+        };
+        let mut current = node(self.root)?;
+        // a valid path visits every node at most once
+        for _ in 0..self.node_map.len() {
+            if current.is_leaf() {
+                return Ok(current.get_value());
+            }
+            current = node(current.try_next_node_id(feature(current)?)?)?;
+        }
+        Err(malformed_tree("cycle in tree"))
+    }
+
+    /// Leaf reached by `x`, numbered as `numbering` says.
+    pub fn predict_leaf(&self, x: &[F], numbering: LeafNumbering) -> usize {
+        let leaf = self.leaf_by(|index| x[index]).id;
+        match numbering {
+            LeafNumbering::NodeId => leaf,
+            LeafNumbering::LeafIndex => self.leaf_indices()[&leaf],
+        }
+    }
+
+    fn leaf_indices(&self) -> &FxHashMap<usize, usize> {
+        self.leaf_indices.get_or_init(|| {
+            let mut leaves: Vec<usize> = self
+                .node_map
+                .values()
+                .filter(|node| node.is_leaf())
+                .map(|node| node.id)
+                .collect();
+            leaves.sort_unstable();
+            leaves
+                .into_iter()
+                .enumerate()
+                .map(|(index, id)| (id, index))
+                .collect()
+        })
+    }
+
+    /// Predict with the value of each split feature looked up by `feature`.
+    pub(crate) fn predict_by(&self, feature: impl Fn(usize) -> F) -> NotNan<F> {
+        self.leaf_by(feature).get_value()
+    }
+
+    fn leaf_by(&self, feature: impl Fn(usize) -> F) -> &TreeNode<F> {
         let mut node = self.node_map.get(&self.root).unwrap();
         while !node.is_leaf() {
-            let feature = NotNan::new(x[node.split_index]).unwrap();
-            let next_node = if feature < node.split_condition {
-                node.left
-            } else {
-                node.right
-            }
-            .and_then(|id| self.node_map.get(&id))
-            .unwrap();
+            let next_node = node
+                .next_node_id(feature(node.split_index))
+                .and_then(|id| self.node_map.get(&id))
+                .unwrap();
             node = next_node;
         }
-        node.get_value()
+        node
+    }
+}
+
+/// Test constructors, so that tests spell out only the fields they care about.
+#[cfg(test)]
+impl<F: Float> TreeNode<F> {
+    /// Split sending `x[split_index] < threshold` to `left`, without default direction.
+    pub(crate) fn split(
+        id: usize,
+        split_index: usize,
+        threshold: F,
+        left: usize,
+        right: usize,
+    ) -> Self {
+        Self {
+            id,
+            split_index,
+            split_condition: NotNan::new(threshold).unwrap(),
+            left: Some(left),
+            right: Some(right),
+            value: NotNan::new(F::from_f64(0.0)).unwrap(),
+            default_left: None,
+            cover: None,
+            internal_value: None,
+            gain: None,
+            hessian: None,
+        }
+    }
+
+    pub(crate) fn leaf(id: usize, value: F) -> Self {
+        Self {
+            left: None,
+            right: None,
+            value: NotNan::new(value).unwrap(),
+            ..Self::split(id, 0, F::from_f64(0.0), 0, 0)
+        }
+    }
+
+    pub(crate) fn with_default_left(mut self, default_left: bool) -> Self {
+        self.default_left = Some(default_left);
+        self
+    }
+
+    pub(crate) fn with_cover(mut self, cover: F) -> Self {
+        self.cover = Some(NotNan::new(cover).unwrap());
+        self
     }
 }
 
@@ -70,100 +387,91 @@ mod test {
     use ordered_float::NotNan;
 
     use crate::{
+        Error,
         map::FxIndexMap,
         tree::{Tree, TreeNode},
     };
 
     #[test]
     fn test_tree() {
-        // Build nodes
-        let mut nodes = FxIndexMap::default();
-        nodes.insert(
-            0,
-            TreeNode {
-                id: 0,
-                split_index: 0,
-                split_condition: NotNan::new(5.0).unwrap(),
-                left: Some(1),
-                right: Some(2),
-                value: NotNan::new(0.0).unwrap(),
-            },
-        );
-        nodes.insert(
-            1,
-            TreeNode {
-                id: 1,
-                split_index: 1,
-                split_condition: NotNan::new(3.0).unwrap(),
-                left: Some(3),
-                right: Some(4),
-                value: NotNan::new(0.0).unwrap(),
-            },
-        );
-        nodes.insert(
-            2,
-            TreeNode {
-                id: 2,
-                split_index: 1,
-                split_condition: NotNan::new(2.0).unwrap(),
-                left: Some(5),
-                right: Some(6),
-                value: NotNan::new(0.0).unwrap(),
-            },
-        );
-        nodes.insert(
-            3,
-            TreeNode {
-                id: 3,
-                split_index: 0,
-                split_condition: NotNan::new(0.0).unwrap(),
-                left: None,
-                right: None,
-                value: NotNan::new(3.0).unwrap(),
-            },
-        );
-        nodes.insert(
-            4,
-            TreeNode {
-                id: 4,
-                split_index: 0,
-                split_condition: NotNan::new(0.0).unwrap(),
-                left: None,
-                right: None,
-                value: NotNan::new(4.0).unwrap(),
-            },
-        );
-        nodes.insert(
-            5,
-            TreeNode {
-                id: 5,
-                split_index: 0,
-                split_condition: NotNan::new(0.0).unwrap(),
-                left: None,
-                right: None,
-                value: NotNan::new(5.0).unwrap(),
-            },
-        );
-        nodes.insert(
-            6,
-            TreeNode {
-                id: 6,
-                split_index: 0,
-                split_condition: NotNan::new(0.0).unwrap(),
-                left: None,
-                right: None,
-                value: NotNan::new(6.0).unwrap(),
-            },
-        );
+        let nodes: FxIndexMap<usize, TreeNode> = [
+            TreeNode::split(0, 0, 5.0, 1, 2),
+            TreeNode::split(1, 1, 3.0, 3, 4),
+            TreeNode::split(2, 1, 2.0, 5, 6),
+            TreeNode::leaf(3, 3.0),
+            TreeNode::leaf(4, 4.0),
+            TreeNode::leaf(5, 5.0),
+            TreeNode::leaf(6, 6.0),
+        ]
+        .into_iter()
+        .map(|node| (node.id, node))
+        .collect();
 
-        let tree = Tree {
-            node_map: nodes,
-            root: 0,
-        };
+        let tree = Tree::new(nodes, 0);
 
         assert_eq!(tree.predict(&[4.0, 2.0]), NotNan::new(3.0).unwrap());
         assert_eq!(tree.predict(&[4.0, 4.0]), NotNan::new(4.0).unwrap());
         assert_eq!(tree.predict(&[6.0, 1.0]), NotNan::new(5.0).unwrap());
         assert_eq!(tree.predict(&[6.0, 3.0]), NotNan::new(6.0).unwrap());
     }
+
+    #[test]
+    fn test_tree_missing_value() {
+        let split = |id, default_left| {
+            TreeNode::split(id, 0, 1.0, id + 1, id + 2).with_default_left(default_left)
+        };
+        let leaf = TreeNode::leaf;
+
+        let tree = Tree::from_nodes(vec![split(0, true), leaf(1, 1.0), leaf(2, 2.0)]);
+        assert_eq!(tree.predict(&[0.0]), NotNan::new(1.0).unwrap());
+        assert_eq!(tree.predict(&[2.0]), NotNan::new(2.0).unwrap());
+        assert_eq!(tree.predict(&[f64::NAN]), NotNan::new(1.0).unwrap());
+
+        let tree = Tree::from_nodes(vec![split(0, false), leaf(1, 1.0), leaf(2, 2.0)]);
+        assert_eq!(tree.predict(&[f64::NAN]), NotNan::new(2.0).unwrap());
+    }
+
+    #[test]
+    fn test_try_predict() {
+        let split = |id, split_index| TreeNode::split(id, split_index, 1.0, 2 * id + 1, 2 * id + 2);
+        let leaf = |id| TreeNode::leaf(id, id as f64);
+
+        let tree = Tree::from_nodes(vec![split(0, 0), split(1, 2), leaf(2), leaf(3), leaf(4)]);
+        assert_eq!(tree.n_features(), 3);
+        assert_eq!(
+            tree.try_predict(&[0.0, 0.0, 2.0]).unwrap(),
+            tree.predict(&[0.0, 0.0, 2.0])
+        );
+        assert_eq!(tree.try_predict(&[2.0]).unwrap(), NotNan::new(2.0).unwrap());
+        assert!(matches!(
+            tree.try_predict(&[0.0]),
+            Err(Error::FeatureIndex {
+                feature: 2,
+                node: 1,
+                actual: 1
+            })
+        ));
+        assert!(matches!(
+            tree.try_predict(&[0.0, 0.0, f64::NAN]),
+            Err(Error::MissingValue {
+                feature: 2,
+                node: 1
+            })
+        ));
+
+        // node 2 is missing
+        let tree = Tree::from_nodes(vec![split(0, 0), leaf(1)]);
+        assert!(matches!(
+            tree.try_predict(&[2.0]),
+            Err(Error::MalformedTree { .. })
+        ));
+        // node 0 points back to itself
+        let mut cyclic = split(0, 0);
+        cyclic.right = Some(0);
+        let tree = Tree::from_nodes(vec![cyclic, leaf(1)]);
+        assert!(matches!(
+            tree.try_predict(&[2.0]),
+            Err(Error::MalformedTree { .. })
+        ));
+    }
 }
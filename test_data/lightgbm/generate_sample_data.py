@@ -30,11 +30,14 @@ def train_model(
     }
     model = lgb.train(params, train_dataset)
     y_pred = model.predict(test_x, raw_score=True)
+    # (rows, trees), outputs interleaved tree by tree
+    leaves = model.predict(test_x, pred_leaf=True)
 
     output_dir.mkdir(exist_ok=True)
     model.save_model(output_dir / "model.txt")
     np.savetxt(output_dir / "X.csv", test_x, delimiter=",")
     np.savetxt(output_dir / "y.csv", y_pred, delimiter=",")
+    np.savetxt(output_dir / "leaves.csv", leaves, delimiter=",", fmt="%d")
 
 
 if __name__ == "__main__":
@@ -29,11 +29,21 @@ def train_model(
     }
     model = xgb.train(params, train_dataset)
     y_pred = model.predict(test_dataset, output_margin=True)
+    # (rows, [outputs,] features + 1, features + 1), flattened per row
+    interactions = model.predict(test_dataset, pred_interactions=True)
+    # (rows, trees), outputs interleaved tree by tree
+    leaves = model.predict(test_dataset, pred_leaf=True)
 
     output_dir.mkdir(exist_ok=True)
     model.save_model(output_dir / "model.json")
     np.savetxt(output_dir / "X.csv", X[n // 2 :], delimiter=",")
     np.savetxt(output_dir / "y.csv", y_pred, delimiter=",")
+    np.savetxt(output_dir / "leaves.csv", leaves, delimiter=",", fmt="%d")
+    np.savetxt(
+        output_dir / "interactions.csv",
+        interactions.reshape(interactions.shape[0], -1),
+        delimiter=",",
+    )
 
 
 if __name__ == "__main__":